@@ -0,0 +1,39 @@
+use arnulf::{BlockingParser, Parser};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::executor::block_on;
+use futures::io::{BufReader, Cursor};
+
+/// A run of passing and failing points, the failures with a yaml block.
+fn document(points: usize) -> String {
+    let mut document = format!("TAP Version 14\n1..{points}\n");
+    for number in 1..=points {
+        match number % 10 {
+            0 => document.push_str(&format!("not ok {number} - caf\u{e9} {number}\n  ---\n  got: 1\n  expected: 2\n  ...\n")),
+            _ => document.push_str(&format!("ok {number} - caf\u{e9} {number}\n")),
+        }
+    }
+    document
+}
+
+fn stream(c: &mut Criterion) {
+    let input = document(10_000);
+
+    let mut group = c.benchmark_group("stream");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    // small reads split details and characters alike, the buffer has to hold on to partial ones
+    for capacity in [16, 4096] {
+        group.bench_function(format!("async reads of {capacity} bytes"), |b| b.iter(|| block_on(async {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(input.as_bytes()));
+            let parser = Parser::new(reader).await.unwrap();
+            parser.test_results().count().await.unwrap()
+        })));
+        group.bench_function(format!("blocking reads of {capacity} bytes"), |b| b.iter(|| {
+            let reader = std::io::BufReader::with_capacity(capacity, input.as_bytes());
+            BlockingParser::new(reader).unwrap().details().count()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream);
+criterion_main!(benches);
@@ -0,0 +1,138 @@
+use std::io::{self, BufRead};
+use crate::decoder::{Decoded, Decoder};
+use crate::{ParseError, ParserOptions, TestDetails, BYTE_ORDER_MARK};
+
+/// Parses TAP from a blocking reader, for callers without an async runtime. It reads the input
+/// bit by bit, just like [`Parser`](crate::Parser), and yields the same details.
+pub struct BlockingParser<R> {
+    reader: R,
+    version: u32,
+    /// How many bytes the header took up.
+    offset: usize,
+    /// The first line, if it wasn't a version line but already part of the run.
+    first_line: Vec<u8>,
+    options: ParserOptions,
+}
+
+impl<R> BlockingParser<R>
+    where
+        R: BufRead,
+{
+    pub fn new(reader: R) -> Result<BlockingParser<R>, ParseError> {
+        Self::with_options(reader, ParserOptions::default())
+    }
+
+    pub fn with_options(mut reader: R, options: ParserOptions) -> Result<BlockingParser<R>, ParseError> {
+        let mut offset = 0;
+        let mut version = 14;
+        let mut first_line = Vec::new();
+
+        if reader.fill_buf()?.starts_with(BYTE_ORDER_MARK) {
+            reader.consume(BYTE_ORDER_MARK.len());
+            offset += BYTE_ORDER_MARK.len();
+        }
+
+        if options.resilient {
+            let mut partial = Vec::new();
+            offset += reader.read_until(b'\n', &mut partial)?;
+        } else {
+            let mut header = Vec::new();
+            offset += reader.read_until(b'\n', &mut header)?;
+            (version, first_line) = options.header_version(header)?;
+            offset -= first_line.len();
+        }
+
+        Ok(BlockingParser {
+            reader,
+            version,
+            offset,
+            first_line,
+            options,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The details of the run, in the order they appear.
+    pub fn details(self) -> impl Iterator<Item = Result<TestDetails, ParseError>> {
+        let mut reader = self.reader;
+        let mut decoder = Decoder::after_header(self.offset, self.first_line, self.options);
+
+        std::iter::from_fn(move || loop {
+            match decoder.decode() {
+                Decoded::Detail(detail) => return Some(detail.map_err(ParseError::from)),
+                Decoded::End => return None,
+                Decoded::NeedData => {}
+            }
+
+            let available = match reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    decoder.read_failed(e);
+                    continue;
+                }
+            };
+            let read = available.len();
+            decoder.feed(available);
+            reader.consume(read);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+    use crate::{ParserBuilder, TestDirective, TestPlan, TestPoint};
+
+    const SAMPLE: &str = "TAP Version 14\n1..3\nok 1 - first\nnot ok 2 - second\n  ---\n  got: 1\n  ...\nok 3 # SKIP\n";
+
+    #[test]
+    fn details_of_sample() {
+        let parser = BlockingParser::new(Cursor::new(SAMPLE)).unwrap();
+        assert_eq!(parser.version(), 14);
+
+        let details = parser.details().collect::<Result<Vec<_>, _>>().unwrap();
+        let skipped = TestPoint {
+            description: None,
+            ..TestPoint::ok("").with_number(3).with_directive(TestDirective::Skip(None))
+        };
+        assert_eq!(details, vec![
+            TestDetails::TestPlan(TestPlan::new(3)),
+            TestDetails::TestPoint(TestPoint::ok("first").with_number(1)),
+            TestDetails::TestPoint(TestPoint::not_ok("second").with_number(2).with_yaml("got: 1\n".to_string())),
+            TestDetails::TestPoint(skipped),
+        ]);
+    }
+
+    #[test]
+    fn details_without_version_line() {
+        let parser = BlockingParser::new(Cursor::new("1..2\nok 1 - first\nnot ok 2 - second\n")).unwrap();
+        assert_eq!(parser.version(), 12);
+
+        let details = parser.details().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(details, vec![
+            TestDetails::TestPlan(TestPlan::new(2)),
+            TestDetails::TestPoint(TestPoint::ok("first").with_number(1)),
+            TestDetails::TestPoint(TestPoint::not_ok("second").with_number(2)),
+        ]);
+    }
+
+    #[test]
+    fn version_overflow_is_an_error() {
+        let result = BlockingParser::new(Cursor::new("TAP version 99999999999\nok 1\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn strict_reports_unparsed_line() {
+        let options = ParserBuilder::new().strict(true).options().clone();
+        let parser = BlockingParser::with_options(Cursor::new("TAP Version 14\nwhat\nok\n"), options).unwrap();
+
+        let error = parser.details().next().unwrap().unwrap_err();
+        assert!(matches!(error, ParseError::Unparsed { offset: 15, .. }), "{error:?}");
+    }
+}
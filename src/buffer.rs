@@ -0,0 +1,148 @@
+use std::str::Utf8Error;
+use bytes::{Buf, BytesMut};
+
+/// The bytes read from a stream that haven't been parsed yet.
+///
+/// Reads come in arbitrary chunks and most of them don't complete a detail, so the buffer keeps
+/// track of how much of itself is known to be valid UTF-8 and only validates what was appended
+/// since. Parsed details are split off the front without moving the rest of the buffer.
+#[derive(Debug, Default)]
+pub(crate) struct LineBuffer {
+    bytes: BytesMut,
+    /// The length of the prefix of `bytes` known to be valid UTF-8, always on a char boundary.
+    valid: usize,
+    /// The invalid bytes that ended the valid prefix, if any turned up.
+    invalid: Option<Utf8Error>,
+    /// How many bytes have been validated in total.
+    #[cfg(test)]
+    scanned: usize,
+}
+
+impl LineBuffer {
+    /// The first `len` bytes, lossily decoded.
+    pub(crate) fn prefix(&self, len: usize) -> String {
+        String::from_utf8_lossy(&self.bytes[..len]).into_owned()
+    }
+
+    /// How many lines end within the first `len` bytes.
+    pub(crate) fn lines(&self, len: usize) -> usize {
+        self.bytes[..len].iter().filter(|&&byte| byte == b'\n').count()
+    }
+
+    /// The raw bytes, to append freshly read input to.
+    pub(crate) fn bytes_mut(&mut self) -> &mut BytesMut {
+        &mut self.bytes
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Terminates the last line, if it isn't already.
+    pub(crate) fn terminate_line(&mut self) {
+        if !self.bytes.ends_with(b"\n") {
+            self.bytes.extend_from_slice(b"\n");
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bytes.clear();
+        self.valid = 0;
+        self.invalid = None;
+    }
+
+    /// Validates what was appended since the last call. The valid prefix is the longest one made
+    /// up of complete characters, a character split at the end of the buffer is left for a later
+    /// read to complete, unless there won't be any more.
+    ///
+    /// Invalid bytes cut the valid prefix short at the last line ending before them, so the
+    /// complete lines preceding them can still be parsed. The error is only returned once those
+    /// have been discarded.
+    pub(crate) fn validate(&mut self, eof: bool) -> Result<(), Utf8Error> {
+        if let Some(error) = self.invalid {
+            return match self.valid {
+                0 => Err(error),
+                _ => Ok(()),
+            };
+        }
+
+        let unvalidated = &self.bytes[self.valid..];
+        #[cfg(test)]
+        {
+            self.scanned += unvalidated.len();
+        }
+
+        match std::str::from_utf8(unvalidated) {
+            Ok(_) => self.valid = self.bytes.len(),
+            // a character split across reads, the rest of it is still coming
+            Err(e) if e.error_len().is_none() && !eof => self.valid += e.valid_up_to(),
+            Err(e) => {
+                let valid = &self.bytes[..self.valid + e.valid_up_to()];
+                self.valid = valid.iter().rposition(|&byte| byte == b'\n').map_or(0, |end| end + 1);
+                self.invalid = Some(e);
+                return self.validate(eof);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The valid prefix of the buffer, as far as [`validate`](Self::validate) got.
+    pub(crate) fn valid_str(&self) -> &str {
+        // SAFETY: every byte up to `valid` has been validated, and discarding from the front keeps
+        // the rest of the prefix valid
+        unsafe { std::str::from_utf8_unchecked(&self.bytes[..self.valid]) }
+    }
+
+    /// Whether invalid bytes follow the valid prefix, so no more input is going to extend it.
+    pub(crate) fn cut_short(&self) -> bool {
+        self.invalid.is_some()
+    }
+
+    /// Discards the first `len` bytes, they have been dealt with. `len` has to fall on a char
+    /// boundary.
+    pub(crate) fn discard(&mut self, len: usize) {
+        self.bytes.advance(len);
+        self.valid = self.valid.saturating_sub(len);
+    }
+
+    /// The first line including its newline, lossily decoded as it might not be valid UTF-8.
+    pub(crate) fn first_line(&self) -> (String, usize) {
+        let len = self.bytes.iter().position(|&byte| byte == b'\n').map_or(self.bytes.len(), |end| end + 1);
+        let line = String::from_utf8_lossy(&self.bytes[..len]).trim_end_matches('\n').to_string();
+        (line, len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partial_reads_are_validated_once() {
+        let input = "ok 1 - caf\u{e9} au lait\n".as_bytes();
+
+        let mut buffer = LineBuffer::default();
+        for byte in input {
+            buffer.bytes_mut().extend_from_slice(&[*byte]);
+            buffer.validate(false).unwrap();
+        }
+
+        assert_eq!(buffer.valid_str(), "ok 1 - caf\u{e9} au lait\n");
+        // the split character is looked at twice, everything else exactly once
+        assert_eq!(buffer.scanned, input.len() + 1);
+    }
+
+    #[test]
+    fn lines_before_invalid_bytes_stay_valid() {
+        let mut buffer = LineBuffer::default();
+        buffer.bytes_mut().extend_from_slice(b"ok 1\nok 2 - \xff\n");
+
+        buffer.validate(false).unwrap();
+        assert_eq!(buffer.valid_str(), "ok 1\n");
+        assert!(buffer.cut_short());
+
+        buffer.discard(5);
+        assert!(buffer.validate(false).is_err());
+    }
+}
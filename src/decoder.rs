@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+use std::io;
+use crate::buffer::LineBuffer;
+#[cfg(feature = "encoding")]
+use crate::encoding::Transcoder;
+use crate::parsing::{is_overflow, parse_detail, parse_detail_at_eof};
+use crate::{ParseError, ParserOptions, Pragma, TestDetails, TestPlan};
+
+/// What the decoder could make of the input it has been fed so far.
+pub(crate) enum Decoded {
+    Detail(io::Result<TestDetails>),
+    /// The buffer doesn't hold a complete detail yet, feed it more input.
+    NeedData,
+    /// The input has ended and every detail has been handed out.
+    End,
+}
+
+/// Turns input into details, independent of how the input is read. Both the async
+/// [`ResultStream`](crate::ResultStream) and the blocking [`BlockingParser`](crate::BlockingParser)
+/// feed it whatever they read.
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    buffer: LineBuffer,
+    /// How many bytes of the input have been consumed before the start of the buffer.
+    offset: usize,
+    eof: bool,
+    options: ParserOptions,
+    /// The text the last detail was parsed from, if [`ParserOptions::keep_raw`] is enabled.
+    raw: String,
+    /// The leading plan, if the run started with one.
+    plan: Option<TestPlan>,
+    /// Whether the run had a plan yet, leading or not.
+    planned: bool,
+    /// How many test points have been decoded.
+    points: usize,
+    /// The number of the line at the start of the buffer, counting from the start of the input.
+    line: usize,
+    /// How deeply the last detail was indented.
+    depth: usize,
+    /// A test point held back while the warning about it is emitted, with its text.
+    pending: Option<(TestDetails, String)>,
+    /// Reading the input failed, reported once everything read before has been decoded.
+    failed: Option<io::Error>,
+    /// Transcodes input that isn't UTF-8, see [`ParserOptions::encoding`].
+    #[cfg(feature = "encoding")]
+    transcoder: Option<Transcoder>,
+}
+
+impl Decoder {
+    /// A decoder for input that starts `offset` bytes in, on the given line, after the header.
+    pub(crate) fn new(offset: usize, line: usize, options: ParserOptions) -> Decoder {
+        #[cfg(feature = "encoding")]
+        let encoding = options.encoding;
+        Decoder {
+            buffer: LineBuffer::default(),
+            offset,
+            eof: false,
+            options,
+            raw: String::new(),
+            plan: None,
+            planned: false,
+            points: 0,
+            line,
+            depth: 0,
+            pending: None,
+            failed: None,
+            #[cfg(feature = "encoding")]
+            transcoder: encoding.map(Transcoder::new),
+        }
+    }
+
+    /// A decoder for the input following the header, which took up the first line unless it wasn't
+    /// a version line and is `first_line` of the input instead.
+    pub(crate) fn after_header(offset: usize, first_line: Vec<u8>, options: ParserOptions) -> Decoder {
+        if first_line.is_empty() {
+            return Decoder::new(offset, 2, options);
+        }
+
+        let mut decoder = Decoder::new(offset, 1, options);
+        decoder.feed(&first_line);
+        decoder
+    }
+
+    /// Appends freshly read bytes, no bytes at all mark the end of the input.
+    pub(crate) fn feed(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            self.eof = true;
+        }
+
+        #[cfg(feature = "encoding")]
+        if let Some(transcoder) = &mut self.transcoder {
+            transcoder.decode_into(bytes, self.buffer.bytes_mut(), self.eof);
+            return;
+        }
+        self.buffer.bytes_mut().extend_from_slice(bytes);
+    }
+
+    /// Takes the next complete detail out of the buffer. Once the input has ended, whatever is
+    /// still waiting for more input is parsed as complete instead, so that every line ends up as a
+    /// detail or an error rather than waiting for input that isn't coming.
+    pub(crate) fn decode(&mut self) -> Decoded {
+        #[cfg(feature = "tracing")]
+        let _entered = crate::trace::span().entered();
+
+        if let Some((detail, raw)) = self.pending.take() {
+            self.raw = raw;
+            return Decoded::Detail(Ok(detail));
+        }
+
+        if self.eof {
+            if self.buffer.is_empty() {
+                return match self.failed.take() {
+                    Some(error) => {
+                        let kind = error.kind();
+                        let error = ParseError::Io { line: Some(self.line), points: self.points, error };
+                        Decoded::Detail(Err(io::Error::new(kind, error)))
+                    }
+                    None => Decoded::End,
+                };
+            }
+            // the end of the stream terminates the last line, even if the producer didn't
+            self.buffer.terminate_line();
+        } else if self.buffer.is_empty() {
+            return Decoded::NeedData;
+        }
+
+        if let Err(e) = self.buffer.validate(self.eof) {
+            // there's no telling where the garbage ends, so give up on the stream
+            self.buffer.clear();
+            self.eof = true;
+            return Decoded::Detail(Err(io::Error::new(io::ErrorKind::InvalidData, e)));
+        }
+        // the lines before invalid bytes are all there is to them
+        let cut_short = self.buffer.cut_short();
+        let string = self.buffer.valid_str();
+
+        // an indented run is parsed as if it weren't indented, as far as the indentation goes
+        let indent = match self.options.allow_indented {
+            true => match string.split_once('\n') {
+                Some((line, _)) => &line[..line.len() - line.trim_start_matches([' ', '\t']).len()],
+                None => return Decoded::NeedData,
+            },
+            false => "",
+        };
+        let (string, run_ended) = match indent {
+            "" => (Cow::Borrowed(string), false),
+            indent => {
+                let (dedented, run_ended) = dedent(string, indent);
+                (Cow::Owned(dedented), run_ended)
+            }
+        };
+        // a run of indented lines ends just like the input does
+        let eof = self.eof || cut_short || run_ended;
+
+        let mut overflow = None;
+        let parsed = match parse_detail(&string, &self.options) {
+            // a detail is only complete once the line after it has arrived, otherwise it
+            // might still continue (a yaml block, more lines of a subtest...)
+            Ok((remaining, detail)) if eof || remaining.contains('\n') => {
+                Some((string.len() - remaining.len(), detail))
+            }
+            // if we need more data, keep reading
+            Ok(_) | Err(nom::Err::Incomplete(_)) if !eof => return Decoded::NeedData,
+            // nothing more is coming, so whatever is still open is as complete as it gets
+            Ok(_) | Err(nom::Err::Incomplete(_)) => parse_detail_at_eof(&string, &self.options)
+                .ok()
+                .map(|(remaining, detail)| (string.len() - remaining.len(), detail)),
+            Err(e) if is_overflow(&e) => {
+                overflow = Some(ParseError::from(e));
+                None
+            }
+            Err(_) => None,
+        };
+        // every line the detail took up lost its indentation
+        let parsed = parsed.map(|(len, detail)| (len + string[..len].matches('\n').count() * indent.len(), detail));
+        self.depth = self.options.depth(indent);
+
+        Decoded::Detail(match parsed {
+            Some((len, detail)) => {
+                let offset = self.offset;
+                self.discard(len);
+                if let TestDetails::Pragma(pragma) = &detail {
+                    self.apply(pragma);
+                }
+                self.options.check(detail, offset).and_then(|detail| self.count(detail))
+            }
+            None => self.unparsed_line(overflow),
+        })
+    }
+
+    /// Follows the pragmas we understand: `strict` makes unparsable lines errors from then on, just
+    /// like [`ParserOptions::strict`], until it is disabled again.
+    fn apply(&mut self, pragma: &Pragma) {
+        match pragma {
+            Pragma::Enable(key) if key == "strict" => self.options.strict = true,
+            Pragma::Disable(key) if key == "strict" => self.options.strict = false,
+            _ => {}
+        }
+    }
+
+    /// Keeps track of the plan and the test points, and holds back a test point that exceeds the
+    /// plan to warn about it first. A run has at most one plan, any other is an error.
+    fn count(&mut self, detail: TestDetails) -> io::Result<TestDetails> {
+        match &detail {
+            // the plans of indented subtests are their own
+            TestDetails::TestPlan(plan) if self.depth == 0 && self.planned => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, ParseError::DuplicatePlan(plan.clone())));
+            }
+            TestDetails::TestPlan(plan) if self.depth == 0 => {
+                self.planned = true;
+                if self.points == 0 {
+                    self.plan = Some(plan.clone());
+                }
+            }
+            TestDetails::TestPoint(_) => {
+                self.points += 1;
+                let Some(plan) = self.plan.as_ref().filter(|plan| self.points > plan.count()) else {
+                    return Ok(detail);
+                };
+                let (planned, got) = (plan.count(), self.points);
+                let empty = planned == 0 && !plan.is_skip_all();
+                self.pending = Some((detail, std::mem::take(&mut self.raw)));
+
+                return if empty {
+                    // an empty plan that doesn't skip the run is a harness bug, not a warning
+                    Err(io::Error::new(io::ErrorKind::InvalidData, ParseError::TestsAfterEmptyPlan))
+                } else if self.options.strict {
+                    Err(io::Error::new(io::ErrorKind::InvalidData, ParseError::ExtraTest { planned, got }))
+                } else {
+                    Ok(TestDetails::ExtraTest { planned, got })
+                };
+            }
+            _ => {}
+        }
+
+        Ok(detail)
+    }
+
+    /// How deeply the last detail was indented, always 0 unless
+    /// [`ParserOptions::allow_indented`] is enabled.
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The text the last detail was parsed from, empty unless [`ParserOptions::keep_raw`] is
+    /// enabled.
+    pub(crate) fn take_raw(&mut self) -> String {
+        std::mem::take(&mut self.raw)
+    }
+
+    /// Reading the input failed, so no more of it is coming. Whatever was read before is decoded
+    /// as if the input had ended there, then the error is reported, noting how far decoding got.
+    pub(crate) fn read_failed(&mut self, error: io::Error) {
+        self.eof = true;
+        self.failed = Some(error);
+    }
+
+    /// Discards the first `len` bytes of the buffer, they have been dealt with.
+    fn discard(&mut self, len: usize) {
+        if self.options.keep_raw {
+            self.raw = self.buffer.prefix(len);
+        }
+        self.line += self.buffer.lines(len);
+        self.buffer.discard(len);
+        self.offset += len;
+    }
+
+    /// Discards the first line of the buffer, which couldn't be parsed, so that parsing can carry
+    /// on after it. A lenient parser passes the line along, a strict one reports it as an error.
+    /// A number too large to count is reported as the `overflow` error either way.
+    fn unparsed_line(&mut self, overflow: Option<ParseError>) -> io::Result<TestDetails> {
+        let (line, len) = self.buffer.first_line();
+        let offset = self.offset;
+        self.discard(len);
+
+        if let Some(error) = overflow {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+
+        self.options.check(TestDetails::Anything(line), offset)
+    }
+}
+
+/// The lines at the start of `s` indented by `indent`, without it, and whether a line that isn't
+/// indented by it follows them.
+fn dedent(s: &str, indent: &str) -> (String, bool) {
+    let mut dedented = String::with_capacity(s.len());
+    for line in s.split_inclusive('\n') {
+        match line.strip_prefix(indent) {
+            Some(line) => dedented.push_str(line),
+            // a partial line might still turn out to be indented
+            None => return (dedented, !indent.starts_with(line)),
+        }
+    }
+    (dedented, false)
+}
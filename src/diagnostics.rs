@@ -0,0 +1,151 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::time::Duration;
+use crate::yaml;
+use crate::TestPoint;
+
+/// The conventional keys of a test point's yaml block.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct Diagnostics {
+    pub message: Option<String>,
+    pub severity: Option<String>,
+    pub at: Option<String>,
+    pub got: Option<String>,
+    pub expected: Option<String>,
+}
+
+impl Diagnostics {
+    /// Picks the conventional keys out of a yaml block, anything else in it is ignored.
+    pub fn from_yaml(yaml: &str) -> Diagnostics {
+        let field = |key| yaml::text(yaml, key).map(|value| unquote(&value));
+
+        Diagnostics {
+            message: field("message"),
+            severity: field("severity"),
+            at: field("at"),
+            got: field("got"),
+            expected: field("expected"),
+        }
+    }
+}
+
+impl TestPoint {
+    /// The conventional keys of the point's yaml block, `None` if it doesn't have one.
+    pub fn diagnostics(&self) -> Option<Diagnostics> {
+        self.yaml.as_deref().map(Diagnostics::from_yaml)
+    }
+
+    /// The yaml blocks of the point, in order. There is usually just one, but producers may stack
+    /// several, which [`TestPoint::yaml`] keeps separated by `...` and `---` lines.
+    pub fn yaml_blocks(&self) -> Vec<&str> {
+        let Some(yaml) = self.yaml.as_deref() else {
+            return Vec::new();
+        };
+
+        let mut blocks = Vec::new();
+        let (mut start, mut offset) = (0, 0);
+        let mut lines = yaml.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            offset += line.len();
+            if line.trim_end() == "..." && lines.peek().is_some_and(|next| next.trim_end() == "---") {
+                blocks.push(&yaml[start..offset - line.len()]);
+                offset += lines.next().map_or(0, str::len);
+                start = offset;
+            }
+        }
+        blocks.push(&yaml[start..]);
+        blocks
+    }
+
+    /// How long the test took, from either `duration_ms` in milliseconds or `duration` in seconds
+    /// in the yaml block, or from an attached `# time=` comment in seconds (or milliseconds with an
+    /// `ms` suffix).
+    pub fn duration(&self) -> Option<Duration> {
+        let seconds = self.yaml_seconds().or_else(|| self.comment_seconds())?;
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+
+    fn yaml_seconds(&self) -> Option<f64> {
+        let yaml = self.yaml.as_deref()?;
+        let number = |key| yaml::scalar(yaml, key)?.parse::<f64>().ok();
+
+        number("duration_ms").map(|millis| millis / 1000.0).or_else(|| number("duration"))
+    }
+
+    fn comment_seconds(&self) -> Option<f64> {
+        let time = self.comments.iter().find_map(|comment| comment.strip_prefix("time="))?;
+
+        match time.strip_suffix("ms") {
+            Some(millis) => millis.parse::<f64>().ok().map(|millis| millis / 1000.0),
+            None => time.parse().ok(),
+        }
+    }
+}
+
+/// Strips the quotes off a quoted scalar.
+fn unquote(value: &str) -> String {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{format, vec};
+    use super::*;
+    use crate::{parse_document_with, ParserOptions, TestDetails};
+
+    #[test]
+    fn diagnostics_from_yaml() {
+        let point = TestPoint::not_ok("broken")
+            .with_yaml("message: \"values differ\"\ngot: 1\nexpected: 2\ndata:\n  extra: true\n".to_string());
+
+        assert_eq!(point.diagnostics(), Some(Diagnostics {
+            message: Some("values differ".to_string()),
+            got: Some("1".to_string()),
+            expected: Some("2".to_string()),
+            ..Diagnostics::default()
+        }));
+        assert_eq!(TestPoint::ok("fine").diagnostics(), None);
+    }
+
+    #[test]
+    fn duration_from_yaml() {
+        let point = TestPoint::ok("slow").with_yaml("duration_ms: 1500\n".to_string());
+        assert_eq!(point.duration(), Some(Duration::from_millis(1500)));
+
+        let point = TestPoint::ok("slow").with_yaml("duration: 0.25\n".to_string());
+        assert_eq!(point.duration(), Some(Duration::from_millis(250)));
+
+        assert_eq!(TestPoint::ok("untimed").duration(), None);
+    }
+
+    #[test]
+    fn duration_from_time_comment() {
+        let options = ParserOptions { attach_diagnostics: true, ..Default::default() };
+        let details = parse_document_with("ok 1 - fast\n# time=0.042\n# all good\nok 2 - slow\n# time=1500ms\n", &options).unwrap();
+
+        let [TestDetails::TestPoint(fast), TestDetails::TestPoint(slow)] = &details[..] else {
+            panic!("expected the comments to be attached, got {details:?}");
+        };
+        assert_eq!(fast.duration(), Some(Duration::from_millis(42)));
+        assert_eq!(fast.comments, vec!["time=0.042".to_string(), "all good".to_string()]);
+        assert_eq!(slow.duration(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn stacked_yaml_blocks() {
+        let input = "not ok 1 - twice\n  ---\n  got: 1\n  ...\n  ---\n  retry:\n    got: 2\n  ...\nok 2\n";
+        let details = parse_document_with(input, &ParserOptions::default()).unwrap();
+
+        let [TestDetails::TestPoint(point), TestDetails::TestPoint(_)] = &details[..] else {
+            panic!("expected both blocks to belong to the first point, got {details:?}");
+        };
+        assert_eq!(point.yaml_blocks(), vec!["got: 1\n", "retry:\n  got: 2\n"]);
+        // the point renders back to the same stacked blocks
+        assert_eq!(format!("{point}\n"), input.strip_suffix("ok 2\n").unwrap());
+        assert_eq!(TestPoint::ok("fine").with_yaml("a: 1\n".to_string()).yaml_blocks(), vec!["a: 1\n"]);
+    }
+}
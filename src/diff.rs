@@ -0,0 +1,47 @@
+use similar::{ChangeTag, TextDiff};
+use crate::TestPoint;
+
+impl TestPoint {
+    /// A line based diff from the `expected` to the `got` value of the point's yaml block, `None`
+    /// unless it has both. Removed lines are marked with `-`, added ones with `+`.
+    pub fn diff(&self) -> Option<String> {
+        let diagnostics = self.diagnostics()?;
+        Some(diff_lines(&diagnostics.expected?, &diagnostics.got?))
+    }
+}
+
+/// Diffs two texts line by line.
+fn diff_lines(expected: &str, got: &str) -> String {
+    let mut diff = String::from("--- expected\n+++ got\n");
+    for change in TextDiff::from_lines(expected, got).iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Equal => ' ',
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+        };
+        diff.push(sign);
+        diff.push_str(change.value().trim_end_matches('\n'));
+        diff.push('\n');
+    }
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_marks_changed_line() {
+        let point = TestPoint::not_ok("lists differ").with_yaml(
+            "got: |\n  one\n  two\n  three\nexpected: |\n  one\n  2\n  three\n".to_string(),
+        );
+
+        assert_eq!(point.diff().unwrap(), "--- expected\n+++ got\n one\n-2\n+two\n three\n");
+        assert_eq!(TestPoint::not_ok("no yaml").diff(), None);
+    }
+
+    #[test]
+    fn diff_without_final_newline() {
+        assert_eq!(diff_lines("one\ntwo", "one\nthree"), "--- expected\n+++ got\n one\n-two\n+three\n");
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{self, Debug};
+use bytes::BytesMut;
+use encoding_rs::{CoderResult, Decoder, Encoding};
+
+/// Transcodes input to UTF-8 as it is read. A character split between two reads is completed by
+/// the second one, malformed input becomes U+FFFD.
+pub(crate) struct Transcoder(Decoder);
+
+impl Transcoder {
+    pub(crate) fn new(encoding: &'static Encoding) -> Transcoder {
+        Transcoder(encoding.new_decoder_without_bom_handling())
+    }
+
+    /// Transcodes `bytes` to UTF-8 and appends them to `buffer`. The `last` bytes of the input
+    /// finish off a character that was left incomplete.
+    pub(crate) fn decode_into(&mut self, mut bytes: &[u8], buffer: &mut BytesMut, last: bool) {
+        let mut decoded = String::new();
+        loop {
+            decoded.reserve(self.0.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len()));
+            let (result, read, _replaced) = self.0.decode_to_string(bytes, &mut decoded, last);
+            bytes = &bytes[read..];
+            if result == CoderResult::InputEmpty {
+                break;
+            }
+        }
+        buffer.extend_from_slice(decoded.as_bytes());
+    }
+}
+
+impl Debug for Transcoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Transcoder").field(&self.0.encoding().name()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use futures::io::{BufReader, Cursor};
+    use futures::TryStreamExt;
+    use crate::{Parser, ParserOptions, TestDetails};
+    use super::*;
+
+    fn descriptions(input: &[u8], encoding: &'static Encoding, capacity: usize) -> Vec<Option<String>> {
+        let options = ParserOptions { encoding: Some(encoding), ..Default::default() };
+
+        let details: Vec<_> = block_on(async {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(input));
+            let parser = Parser::with_options(reader, options).await.unwrap();
+            parser.test_results().try_collect().await.unwrap()
+        });
+        details
+            .into_iter()
+            .map(|detail| match detail {
+                TestDetails::TestPoint(point) => point.description,
+                other => panic!("expected a test point, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_latin1_description() {
+        let latin1 = Encoding::for_label(b"latin1").unwrap();
+
+        let descriptions = descriptions(b"TAP Version 14\nok 1 - caf\xe9\n", latin1, 64);
+        assert_eq!(descriptions, vec![Some("café".to_string())]);
+    }
+
+    #[test]
+    fn parse_characters_split_between_reads() {
+        // each of the characters takes up two bytes, reads of three bytes split them
+        let descriptions = descriptions(b"TAP Version 14\nok 1 - \x93\xfa\x96\x7b\n", encoding_rs::SHIFT_JIS, 3);
+        assert_eq!(descriptions, vec![Some("日本".to_string())]);
+    }
+}
@@ -0,0 +1,129 @@
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter};
+use core::str::Utf8Error;
+#[cfg(feature = "std")]
+use std::{io, process::ExitStatus};
+use crate::parsing::{PLAN_COUNT_TOO_LARGE, TEST_NUMBER_TOO_LARGE, VERSION_TOO_LARGE};
+use crate::TestPlan;
+#[cfg(feature = "std")]
+use crate::TestSuite;
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The plan declared more tests than can be counted, holds the offending count.
+    PlanCountOverflow(String),
+    /// A test point was numbered higher than can be counted, holds the offending number.
+    TestNumberOverflow(String),
+    /// The version line announced a version higher than can be counted, holds the offending
+    /// version.
+    VersionOverflow(String),
+    /// A run declared a second plan, holds the second one.
+    DuplicatePlan(TestPlan),
+    /// The input didn't match anything we know how to parse, holds the offending input.
+    Malformed(String),
+    /// The input isn't valid UTF-8.
+    InvalidUtf8(Utf8Error),
+    /// Reading the input failed. A stream failing partway notes the line it was reading and how
+    /// many test points it had parsed by then.
+    #[cfg(feature = "std")]
+    Io { line: Option<usize>, points: usize, error: io::Error },
+    /// A line of a stream couldn't be parsed, holds the line and its byte offset in the stream.
+    Unparsed { line: String, offset: usize },
+    /// A strict parser got more test points than the leading plan announced.
+    ExtraTest { planned: usize, got: usize },
+    /// Test points followed a `1..0` plan that doesn't skip the run.
+    TestsAfterEmptyPlan,
+    /// Collecting the test points of a stream failed at the detail with the given index, after
+    /// `points` test points had been collected. Holds the line that couldn't be parsed, if that's
+    /// what failed.
+    #[cfg(feature = "std")]
+    CollectFailed { points: usize, index: usize, line: Option<String>, error: io::Error },
+    /// Reading a stream failed partway, holds what had been parsed up to then.
+    #[cfg(feature = "std")]
+    Interrupted { suite: Box<TestSuite>, error: io::Error },
+    /// The process producing the TAP exited unsuccessfully without reporting any failures.
+    #[cfg(feature = "std")]
+    ProcessFailed(ExitStatus),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::PlanCountOverflow(count) => write!(f, "plan count {count} is too large"),
+            ParseError::TestNumberOverflow(number) => write!(f, "test number {number} is too large"),
+            ParseError::VersionOverflow(version) => write!(f, "TAP version {version} is too large"),
+            ParseError::DuplicatePlan(plan) => write!(f, "duplicate plan {}..{}", plan.start, plan.end),
+            ParseError::Malformed(input) => write!(f, "malformed input: {input:?}"),
+            ParseError::InvalidUtf8(err) => write!(f, "input is not valid utf-8: {err}"),
+            #[cfg(feature = "std")]
+            ParseError::Io { line: Some(line), points, error } => {
+                write!(f, "failed to read input after {points} test points on line {line}: {error}")
+            }
+            #[cfg(feature = "std")]
+            ParseError::Io { line: None, error, .. } => write!(f, "failed to read input: {error}"),
+            #[cfg(feature = "std")]
+            ParseError::CollectFailed { points, index, line: Some(line), error } => {
+                write!(f, "failed at detail {index} ({line:?}) after {points} test points: {error}")
+            }
+            #[cfg(feature = "std")]
+            ParseError::CollectFailed { points, index, line: None, error } => {
+                write!(f, "failed at detail {index} after {points} test points: {error}")
+            }
+            #[cfg(feature = "std")]
+            ParseError::Interrupted { suite, error } => {
+                write!(f, "stream failed after {} details: {error}", suite.details.len())
+            }
+            ParseError::Unparsed { line, offset } => write!(f, "could not parse line at byte {offset}: {line:?}"),
+            ParseError::ExtraTest { planned, got } => write!(f, "got test point {got}, but only {planned} were planned"),
+            ParseError::TestsAfterEmptyPlan => write!(f, "got test points after a plan of no tests"),
+            #[cfg(feature = "std")]
+            ParseError::ProcessFailed(status) => write!(f, "process failed without reporting a failure: {status}"),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ParseError::InvalidUtf8(err) => Some(err),
+            #[cfg(feature = "std")]
+            ParseError::Io { error: err, .. }
+            | ParseError::CollectFailed { error: err, .. }
+            | ParseError::Interrupted { error: err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> Self {
+        // the streams can only report io errors, but some of them are parse errors in disguise
+        if err.get_ref().is_some_and(|inner| inner.is::<ParseError>()) {
+            return *err.into_inner().unwrap().downcast::<ParseError>().unwrap();
+        }
+        ParseError::Io { line: None, points: 0, error: err }
+    }
+}
+
+impl From<nom::Err<nom::error::Error<&str>>> for ParseError {
+    fn from(err: nom::Err<nom::error::Error<&str>>) -> Self {
+        match err {
+            nom::Err::Failure(err) if err.code == PLAN_COUNT_TOO_LARGE => {
+                ParseError::PlanCountOverflow(err.input.to_string())
+            }
+            nom::Err::Failure(err) if err.code == TEST_NUMBER_TOO_LARGE => {
+                ParseError::TestNumberOverflow(err.input.to_string())
+            }
+            nom::Err::Failure(err) if err.code == VERSION_TOO_LARGE => {
+                ParseError::VersionOverflow(err.input.to_string())
+            }
+            nom::Err::Error(err) | nom::Err::Failure(err) => {
+                // only report the offending line, the rest of the input is noise
+                let line = err.input.lines().next().unwrap_or_default();
+                ParseError::Malformed(line.to_string())
+            }
+            nom::Err::Incomplete(_) => ParseError::Malformed(String::new()),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Gzip compressed input, like archived `.tap.gz` logs. The input is decompressed as it is read and
+//! parsed by the same streaming parsers as plain input.
+
+use std::io::{BufReader, Read};
+use async_compression::futures::bufread::GzipDecoder;
+use flate2::read::MultiGzDecoder;
+use futures::{AsyncRead, TryStreamExt};
+use crate::{BlockingParser, ParseError, Parser, TestSuite};
+
+/// Parses gzip compressed TAP. Concatenated members, as appending to a `.gz` file produces, are
+/// parsed as one run.
+pub fn parse_gzip(reader: impl Read) -> Result<TestSuite, ParseError> {
+    let parser = BlockingParser::new(BufReader::new(MultiGzDecoder::new(reader)))?;
+    let version = parser.version();
+    let details = parser.details().collect::<Result<_, _>>()?;
+
+    Ok(TestSuite::from_details(version, details))
+}
+
+/// Parses gzip compressed TAP read asynchronously, like [`parse_gzip`].
+pub async fn parse_gzip_async(reader: impl AsyncRead + Unpin) -> Result<TestSuite, ParseError> {
+    let mut decoder = GzipDecoder::new(futures::io::BufReader::new(reader));
+    decoder.multiple_members(true);
+
+    let parser = Parser::new(futures::io::BufReader::new(decoder)).await?;
+    let version = parser.version;
+    let details = parser.test_results().try_collect().await?;
+
+    Ok(TestSuite::from_details(version, details))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use futures::executor::block_on;
+    use super::*;
+
+    const SAMPLE: &str = "TAP Version 14\n1..3\nok 1 - first\nnot ok 2 - second\n  ---\n  got: 1\n  ...\nok 3 # SKIP\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn parse_compressed_sample() {
+        let compressed = gzip(SAMPLE.as_bytes());
+
+        let suite = parse_gzip(&compressed[..]).unwrap();
+        assert_eq!(suite, TestSuite::parse(SAMPLE).unwrap());
+
+        let suite = block_on(parse_gzip_async(&compressed[..])).unwrap();
+        assert_eq!(suite, TestSuite::parse(SAMPLE).unwrap());
+    }
+
+    #[test]
+    fn parse_concatenated_members() {
+        let (first, second) = SAMPLE.split_at(SAMPLE.find("not ok").unwrap());
+        let mut compressed = gzip(first.as_bytes());
+        compressed.extend(gzip(second.as_bytes()));
+
+        let suite = parse_gzip(&compressed[..]).unwrap();
+        assert_eq!(suite, TestSuite::parse(SAMPLE).unwrap());
+
+        let suite = block_on(parse_gzip_async(&compressed[..])).unwrap();
+        assert_eq!(suite, TestSuite::parse(SAMPLE).unwrap());
+    }
+
+    #[test]
+    fn trailing_garbage_is_rejected() {
+        let mut compressed = gzip(SAMPLE.as_bytes());
+        compressed.extend(b"garbage");
+
+        assert!(parse_gzip(&compressed[..]).is_err());
+        assert!(block_on(parse_gzip_async(&compressed[..])).is_err());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let compressed = gzip(SAMPLE.as_bytes());
+        let truncated = &compressed[..compressed.len() - 12];
+
+        assert!(parse_gzip(truncated).is_err());
+        assert!(block_on(parse_gzip_async(truncated)).is_err());
+    }
+
+    #[test]
+    fn parse_corrupted() {
+        let mut compressed = gzip(SAMPLE.as_bytes());
+        let last = compressed.len() - 5;
+        compressed[last] ^= 0xff;
+
+        assert!(matches!(parse_gzip(&compressed[..]), Err(ParseError::Io { .. })));
+        assert!(matches!(block_on(parse_gzip_async(&compressed[..])), Err(ParseError::Io { .. })));
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::{self, Write};
+use crate::{TestDirective, TestOutcome, TestPoint, TestSuite};
+
+/// Renders the suite as a JUnit XML `<testsuite>`, for CI systems that don't understand TAP. The
+/// suite and the class of its test cases are named `tap`.
+pub fn to_junit(suite: &TestSuite) -> String {
+    to_junit_named(suite, "tap")
+}
+
+/// Like [`to_junit`], naming the suite and the class of its test cases `name`, which is what CI
+/// systems like Jenkins and GitLab group the results by.
+pub fn to_junit_named(suite: &TestSuite, name: &str) -> String {
+    let mut xml = String::new();
+    write_test_suite(&mut xml, suite, &escape(name)).expect("writing to a String can't fail");
+    xml
+}
+
+fn write_test_suite(xml: &mut String, suite: &TestSuite, name: &str) -> fmt::Result {
+    let summary = suite.summary();
+    writeln!(
+        xml,
+        r#"<testsuite name="{name}" tests="{}" failures="{}" skipped="{}">"#,
+        summary.total, summary.failed, summary.skipped,
+    )?;
+    for (index, point) in suite.test_points().into_iter().enumerate() {
+        write_test_case(xml, name, index + 1, point)?;
+    }
+    writeln!(xml, "</testsuite>")
+}
+
+fn write_test_case(xml: &mut String, classname: &str, position: usize, point: &TestPoint) -> fmt::Result {
+    let number = point.test_number.unwrap_or(position);
+    let name = match &point.description {
+        Some(description) => escape(description),
+        None => format!("test {number}"),
+    };
+    write!(xml, r#"  <testcase classname="{classname}" name="{name}""#)?;
+
+    match point.outcome() {
+        TestOutcome::Failed => {
+            writeln!(xml, ">")?;
+            // the yaml diagnostics are the closest thing TAP has to a failure body
+            let body = point.yaml.as_deref().map(escape).unwrap_or_default();
+            writeln!(xml, r#"    <failure message="{name}">{body}</failure>"#)?;
+            writeln!(xml, "  </testcase>")
+        }
+        TestOutcome::Skipped => {
+            let reason = match &point.directive {
+                Some(TestDirective::Skip(Some(reason))) => escape(reason),
+                _ => String::new(),
+            };
+            writeln!(xml, ">")?;
+            writeln!(xml, r#"    <skipped message="{reason}"/>"#)?;
+            writeln!(xml, "  </testcase>")
+        }
+        _ => writeln!(xml, "/>"),
+    }
+}
+
+/// Escapes `text` for use in XML. Characters XML 1.0 doesn't allow at all, like the escape
+/// character starting a color code, are dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars().filter(|&c| is_xml_char(c)) {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether XML 1.0 allows `c`, which rules out most control characters.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failing_point() {
+        let input = "TAP Version 14\n1..3\nok 1 - passes\nnot ok 2 - a < b\n  ---\n  got: 2\n  ...\nok 3 - later # SKIP no network\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        let expected = r#"<testsuite name="tap" tests="3" failures="1" skipped="1">
+  <testcase classname="tap" name="passes"/>
+  <testcase classname="tap" name="a &lt; b">
+    <failure message="a &lt; b">got: 2
+</failure>
+  </testcase>
+  <testcase classname="tap" name="later">
+    <skipped message="no network"/>
+  </testcase>
+</testsuite>
+"#;
+        assert_eq!(to_junit(&suite), expected);
+    }
+
+    #[test]
+    fn named_suite_without_control_characters() {
+        let input = "TAP Version 14\nnot ok 1 - \x1b[31mred\x1b[0m\n  ---\n  got: \"\x07\"\n  ...\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        let xml = to_junit_named(&suite, "unit & more");
+        assert!(xml.starts_with(r#"<testsuite name="unit &amp; more" "#));
+        assert!(xml.contains(r#"<testcase classname="unit &amp; more" name="[31mred[0m">"#), "{xml}");
+        assert!(xml.contains("got: &quot;&quot;"), "{xml}");
+        assert!(!xml.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\t' | '\r')));
+    }
+}
@@ -1,10 +1,55 @@
 #![allow(dead_code)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use futures::{AsyncBufReadExt, Stream, AsyncReadExt};
+extern crate alloc;
+
+use alloc::{borrow::Cow, format, string::{String, ToString}, vec::Vec};
+use core::{cmp::Ordering, fmt::{self, Display}, str::FromStr};
+#[cfg(feature = "std")]
+use futures::{AsyncBufRead, AsyncBufReadExt, Stream, ready};
+#[cfg(feature = "std")]
 use std::{io::Result, pin::Pin, task::{Context, Poll}, io};
-use std::future::Future;
+#[cfg(feature = "std")]
 use pin_project::pin_project;
-use crate::parsing::{parse_test_points, parse_version};
+#[cfg(feature = "std")]
+use crate::decoder::{Decoded, Decoder};
+use crate::parsing::{parse_test_point, strip_indentation};
+#[cfg(feature = "std")]
+use crate::parsing::parse_version;
+
+#[cfg(feature = "std")]
+pub use crate::blocking::BlockingParser;
+pub use crate::diagnostics::Diagnostics;
+#[cfg(feature = "encoding")]
+pub use encoding_rs::Encoding;
+pub use crate::error::ParseError;
+#[cfg(feature = "gzip")]
+pub use crate::gzip::{parse_gzip, parse_gzip_async};
+#[cfg(feature = "junit")]
+pub use crate::junit::{to_junit, to_junit_named};
+pub use crate::parsing::{parse_document, parse_document_spanned, parse_document_with, parse_test_point_ref, parse_test_point_refs, parse_yaml};
+#[cfg(feature = "std")]
+pub use crate::pretty::print_summary;
+#[cfg(feature = "std")]
+pub use crate::process::run_command;
+#[cfg(feature = "std")]
+pub use crate::replay::replay;
+#[cfg(feature = "std")]
+pub use crate::report::to_json_report;
+#[cfg(feature = "runtime")]
+pub use crate::runtime::spawn_parser;
+#[cfg(feature = "std")]
+pub use crate::stream::{parse_all, FallibleResultStream, IndentedDetails, PeekableResultStream, RawDetails};
+#[cfg(feature = "std")]
+pub use crate::subtest::{split_subtests, SubtestGroup};
+#[cfg(feature = "std")]
+pub use crate::suite::{is_stable, merge, parse_bytes, unstable_numbers, Partitioned, SuiteBuilder, Summary, TestSuite};
+#[cfg(feature = "std")]
+pub use crate::validate::{check_numbers, validate, NumberAnomaly, Violation};
+#[cfg(feature = "notify")]
+pub use crate::watch::watch;
+#[cfg(feature = "std")]
+pub use crate::writer::Writer;
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum TestDetails {
@@ -12,20 +57,92 @@ pub enum TestDetails {
     BailOut(BailOut),
     TestPlan(TestPlan),
     Pragma(Pragma),
-    Comment(String),
+    Comment(Comment),
     Empty,
     Anything(String),
-    // todo: implement subtest
+    Subtest(Subtest),
+    /// A warning that more test points arrived than the leading plan announced, emitted right
+    /// before the first test point too many and every one after it.
+    ExtraTest { planned: usize, got: usize },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Subtest {
+    /// The name announced by a `# Subtest: name` comment before the subtest.
+    pub name: Option<String>,
+    pub details: Vec<TestDetails>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct Comment(pub String);
 
+impl Comment {
+    /// Whether the comment carries meaning for the run rather than being free text, i.e. it marks
+    /// a subtest or notes a TODO.
+    pub fn is_diagnostic(&self) -> bool {
+        let text = self.0.trim_start();
+        text.starts_with("Subtest:") || text.starts_with("TODO")
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
-pub struct TestPlan(pub usize);
+pub struct TestPlan {
+    /// The number of the first planned test, 1 unless only part of a run was planned.
+    pub start: usize,
+    /// The number of the last planned test.
+    pub end: usize,
+    /// A comment directly preceding the plan, when the parser is asked to attach those.
+    pub label: Option<String>,
+    /// Why the tests were skipped, given as `1..0 # reason`.
+    pub reason: Option<String>,
+    /// A directive for the whole run, `1..0 # SKIP` means all of it was skipped.
+    pub directive: Option<TestDirective>,
+}
+
+impl TestPlan {
+    pub fn new(count: usize) -> TestPlan {
+        TestPlan {
+            start: 1,
+            end: count,
+            label: None,
+            reason: None,
+            directive: None,
+        }
+    }
+
+    /// How many tests are planned.
+    pub fn count(&self) -> usize {
+        match self.end.checked_sub(self.start) {
+            Some(span) => span.saturating_add(1),
+            // a plan like `1..0` announces that there are no tests
+            None => 0,
+        }
+    }
+
+    /// Whether the plan was deliberately not run, like `1..0 # SKIP no database` or
+    /// `1..0 # Skipped: no database`.
+    pub fn is_skip(&self) -> bool {
+        let skipped_reason = self.reason.as_deref().is_some_and(|reason| {
+            reason.get(..4).is_some_and(|keyword| keyword.eq_ignore_ascii_case("skip"))
+        });
+        matches!(self.directive, Some(TestDirective::Skip(_))) || skipped_reason
+    }
 
+    /// Whether the plan is marked as not done yet, like `1..0 # TODO not written yet`.
+    pub fn is_todo(&self) -> bool {
+        matches!(self.directive, Some(TestDirective::Todo(_)))
+    }
+
+    /// Whether the plan skips the whole run, like `1..0 # SKIP no database` or
+    /// `1..0 # Skipped: no database`.
+    pub fn is_skip_all(&self) -> bool {
+        self.count() == 0 && self.is_skip()
+    }
+}
+
+/// A run giving up, with the reason if one was given.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
-pub struct BailOut(pub String);
+pub struct BailOut(pub Option<String>);
 
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -35,80 +152,491 @@ pub enum Pragma {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TestPoint {
     pub status: bool,
     pub test_number: Option<usize>,
     pub description: Option<String>,
     pub directive: Option<TestDirective>,
     pub yaml: Option<String>,
+    /// The yaml block was cut off by the end of the input before it was closed.
+    pub truncated_yaml: bool,
+    /// Comment lines directly following the test point, only collected when
+    /// [`ParserOptions::attach_diagnostics`] is enabled.
+    pub comments: Vec<String>,
+    /// Metadata attached after parsing, e.g. the id of the CI run. The parser always leaves it
+    /// `None`.
+    #[cfg(feature = "serde")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Value>,
 }
+
+/// A [`TestPoint`] borrowing from the input it was parsed from, to parse a document that is
+/// already in memory with as few allocations as possible.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct TestPointRef<'a> {
+    pub status: bool,
+    pub test_number: Option<usize>,
+    pub description: Option<&'a str>,
+    /// Directives are rare enough that they aren't worth borrowing.
+    pub directive: Option<TestDirective>,
+    /// The yaml block as it appears in the input, still indented.
+    pub yaml: Option<&'a str>,
+    pub truncated_yaml: bool,
+}
+
+impl TestPointRef<'_> {
+    pub fn to_owned(&self) -> TestPoint {
+        TestPoint {
+            status: self.status,
+            test_number: self.test_number,
+            description: self.description.map(str::to_string),
+            directive: self.directive.clone(),
+            yaml: self.yaml.map(|yaml| strip_indentation(yaml, "  ")),
+            truncated_yaml: self.truncated_yaml,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TestDirective {
     Todo(Option<String>), Skip(Option<String>)
 }
+
+impl Display for TestDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (keyword, reason) = match self {
+            TestDirective::Todo(reason) => ("TODO", reason),
+            TestDirective::Skip(reason) => ("SKIP", reason),
+        };
+
+        write!(f, "# {keyword}")?;
+        if let Some(reason) = reason {
+            write!(f, " {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Skipped,
+    /// A todo test that failed, as it was expected to.
+    TodoExpectedFail,
+    /// A todo test that passed, it probably shouldn't be todo anymore.
+    TodoUnexpectedPass,
+}
+
+impl TestPoint {
+    pub fn ok(description: impl Into<String>) -> TestPoint {
+        TestPoint::new(true, description.into())
+    }
+
+    pub fn not_ok(description: impl Into<String>) -> TestPoint {
+        TestPoint::new(false, description.into())
+    }
+
+    fn new(status: bool, description: String) -> TestPoint {
+        TestPoint {
+            status,
+            test_number: None,
+            description: Some(description),
+            directive: None,
+            yaml: None,
+            truncated_yaml: false,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        }
+    }
+
+    pub fn with_number(mut self, test_number: usize) -> TestPoint {
+        self.test_number = Some(test_number);
+        self
+    }
+
+    pub fn with_directive(mut self, directive: TestDirective) -> TestPoint {
+        self.directive = Some(directive);
+        self
+    }
+
+    /// Attaches a yaml block, given without the indentation and `---`/`...` markers.
+    pub fn with_yaml(mut self, yaml: String) -> TestPoint {
+        self.yaml = Some(yaml);
+        self
+    }
+
+    /// Classifies the point taking its directive into account, a `not ok` isn't necessarily a failure.
+    pub fn outcome(&self) -> TestOutcome {
+        match (&self.directive, self.status) {
+            (None, true) => TestOutcome::Passed,
+            (None, false) => TestOutcome::Failed,
+            (Some(TestDirective::Skip(_)), _) => TestOutcome::Skipped,
+            (Some(TestDirective::Todo(_)), false) => TestOutcome::TodoExpectedFail,
+            (Some(TestDirective::Todo(_)), true) => TestOutcome::TodoUnexpectedPass,
+        }
+    }
+
+    /// Whether the point counts as a success for the run, the way harnesses compute their exit
+    /// status: only a `not ok` without a directive fails the run.
+    pub fn is_successful(&self) -> bool {
+        self.status || self.directive.is_some()
+    }
+
+    /// Compares the status, number, description and directive, ignoring yaml and comments.
+    pub fn eq_ignoring_diagnostics(&self, other: &TestPoint) -> bool {
+        self.status == other.status
+            && self.test_number == other.test_number
+            && self.description == other.description
+            && self.directive == other.directive
+    }
+}
+
+impl Display for TestPoint {
+    /// Renders the point as TAP, including its yaml block.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.status { "ok" } else { "not ok" })?;
+        if let Some(number) = self.test_number {
+            write!(f, " {number}")?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, " - {description}")?;
+        }
+        if let Some(directive) = &self.directive {
+            write!(f, " {directive}")?;
+        }
+        if let Some(yaml) = &self.yaml {
+            f.write_str("\n  ---")?;
+            for line in yaml.lines() {
+                write!(f, "\n  {line}")?;
+            }
+            f.write_str("\n  ...")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Orders test points by number, with unnumbered points after all numbered ones, then by
+/// description. The remaining fields only break ties to stay consistent with `Eq`.
+impl Ord for TestPoint {
+    fn cmp(&self, other: &TestPoint) -> Ordering {
+        let number = |point: &TestPoint| (point.test_number.is_none(), point.test_number);
+
+        number(self)
+            .cmp(&number(other))
+            .then_with(|| self.description.cmp(&other.description))
+            .then_with(|| self.status.cmp(&other.status))
+            .then_with(|| self.directive.cmp(&other.directive))
+            .then_with(|| self.yaml.cmp(&other.yaml))
+            .then_with(|| self.truncated_yaml.cmp(&other.truncated_yaml))
+            .then_with(|| self.comments.cmp(&other.comments))
+    }
+}
+
+impl PartialOrd for TestPoint {
+    fn partial_cmp(&self, other: &TestPoint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for TestPoint {
+    type Err = ParseError;
+
+    /// Parses a single test point line, optionally followed by its yaml block. Anything left over
+    /// is an error rather than being ignored.
+    fn from_str(s: &str) -> core::result::Result<TestPoint, ParseError> {
+        let s = if s.ends_with('\n') { Cow::Borrowed(s) } else { Cow::Owned(format!("{s}\n")) };
+
+        let (remaining, point) = parse_test_point(&s)?;
+        if !remaining.is_empty() {
+            let leftover = remaining.lines().next().unwrap_or_default();
+            return Err(ParseError::Malformed(leftover.to_string()));
+        }
+
+        Ok(point)
+    }
+}
 mod parsing;
+#[cfg(feature = "std")]
+mod blocking;
+#[cfg(feature = "std")]
+mod buffer;
+#[cfg(feature = "std")]
+mod decoder;
+mod diagnostics;
+#[cfg(feature = "diff")]
+mod diff;
+#[cfg(feature = "encoding")]
+mod encoding;
+mod error;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "junit")]
+mod junit;
+#[cfg(feature = "std")]
+mod pretty;
+#[cfg(feature = "std")]
+mod process;
+#[cfg(feature = "std")]
+mod replay;
+#[cfg(feature = "std")]
+mod report;
+#[cfg(feature = "runtime")]
+mod runtime;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod subtest;
+#[cfg(feature = "std")]
+mod suite;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "std")]
+mod validate;
+#[cfg(feature = "notify")]
+mod watch;
+#[cfg(feature = "std")]
+mod writer;
+mod yaml;
+
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    /// The encoding of the input, it gets transcoded to UTF-8 before parsing. Input is expected to
+    /// be UTF-8 if unset. The input is split into lines before it is transcoded, so the encoding
+    /// has to be ASCII compatible.
+    #[cfg(feature = "encoding")]
+    pub encoding: Option<&'static Encoding>,
+    /// Attach a comment directly preceding the plan to it as its label, instead of emitting it on
+    /// its own.
+    pub plan_labels: bool,
+    /// Start parsing from an arbitrary point of a run, e.g. when tailing a live log. The version
+    /// line isn't required and the first line is skipped, as it is likely only partially there.
+    pub resilient: bool,
+    /// Reject lines that aren't valid TAP instead of passing them along as
+    /// [`TestDetails::Anything`].
+    pub strict: bool,
+    /// The TAP version the input has to declare, any version is accepted if unset.
+    pub expected_version: Option<u32>,
+    /// Attach comment lines directly following a test point to it as [`TestPoint::comments`],
+    /// instead of emitting them on their own.
+    pub attach_diagnostics: bool,
+    /// How far subtests are indented relative to their parent.
+    pub subtest_indent: Indent,
+    /// Keep the text each detail was parsed from, see [`ResultStream::raw_details`].
+    pub keep_raw: bool,
+    /// Accept details indented as a whole, e.g. the body of a subtest read on its own. How deeply
+    /// they were indented, in multiples of [`ParserOptions::subtest_indent`], is available through
+    /// [`ResultStream::with_depth`].
+    pub allow_indented: bool,
+}
+
+/// One level of indentation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Indent {
+    Spaces(usize),
+    Tab,
+}
+
+impl Default for Indent {
+    /// Four spaces, as the spec recommends.
+    fn default() -> Indent {
+        Indent::Spaces(4)
+    }
+}
+
+impl Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Indent::Spaces(count) => write!(f, "{:count$}", ""),
+            Indent::Tab => f.write_str("\t"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ParserOptions {
+    /// How many levels of subtest indentation `indent` makes up.
+    fn depth(&self, indent: &str) -> usize {
+        match self.subtest_indent {
+            Indent::Spaces(0) => 0,
+            Indent::Spaces(width) => indent.len() / width,
+            Indent::Tab => indent.matches('\t').count(),
+        }
+    }
+
+    /// The version announced by the header line, checked against the expected version. A run
+    /// without a version line predates TAP 13, its first line is handed back to be parsed like the
+    /// rest of it.
+    fn header_version(&self, header: Vec<u8>) -> io::Result<(u32, Vec<u8>)> {
+        let parsed = core::str::from_utf8(&header).map(parse_version);
+        let (version, first_line) = match parsed {
+            Ok(Ok((_, version))) => (version, Vec::new()),
+            Ok(Err(e @ nom::Err::Failure(_))) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, ParseError::from(e)));
+            }
+            // an empty run is a valid run without any tests, just like any other run without a
+            // version line
+            Ok(Err(_)) | Err(_) => (12, header),
+        };
+
+        match self.expected_version {
+            Some(expected) if version != expected => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected TAP version {expected}, got {version}"),
+            )),
+            _ => Ok((version, first_line)),
+        }
+    }
+
+    /// Rejects details that aren't allowed with these options, `offset` is where the detail
+    /// started in the input.
+    fn check(&self, detail: TestDetails, offset: usize) -> io::Result<TestDetails> {
+        match detail {
+            TestDetails::Anything(line) if self.strict => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ParseError::Unparsed { line, offset },
+            )),
+            detail => {
+                #[cfg(feature = "tracing")]
+                trace::detail(&detail);
+                Ok(detail)
+            }
+        }
+    }
+}
 
-struct Parser<T> {
+/// The UTF-8 encoded `\u{FEFF}`.
+#[cfg(feature = "std")]
+const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+#[cfg(feature = "std")]
+pub struct Parser<T> {
     stream: T,
+    version: u32,
+    /// How many bytes the header took up.
+    offset: usize,
+    /// The first line, if it wasn't a version line but already part of the run.
+    first_line: Vec<u8>,
+    options: ParserOptions,
 }
 
+/// Configures a [`Parser`] one option at a time. The defaults are lenient and accept any TAP
+/// version.
+#[derive(Debug, Clone, Default)]
+pub struct ParserBuilder {
+    options: ParserOptions,
+}
+
+impl ParserBuilder {
+    pub fn new() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
+    #[cfg(feature = "encoding")]
+    pub fn encoding(mut self, encoding: &'static Encoding) -> ParserBuilder {
+        self.options.encoding = Some(encoding);
+        self
+    }
+
+    pub fn plan_labels(mut self, plan_labels: bool) -> ParserBuilder {
+        self.options.plan_labels = plan_labels;
+        self
+    }
 
+    pub fn resilient(mut self, resilient: bool) -> ParserBuilder {
+        self.options.resilient = resilient;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> ParserBuilder {
+        self.options.strict = strict;
+        self
+    }
+
+    pub fn expected_version(mut self, version: Option<u32>) -> ParserBuilder {
+        self.options.expected_version = version;
+        self
+    }
+
+    pub fn attach_diagnostics(mut self, attach_diagnostics: bool) -> ParserBuilder {
+        self.options.attach_diagnostics = attach_diagnostics;
+        self
+    }
+
+    pub fn subtest_indent(mut self, indent: Indent) -> ParserBuilder {
+        self.options.subtest_indent = indent;
+        self
+    }
+
+    pub fn keep_raw(mut self, keep_raw: bool) -> ParserBuilder {
+        self.options.keep_raw = keep_raw;
+        self
+    }
+
+    pub fn allow_indented(mut self, allow_indented: bool) -> ParserBuilder {
+        self.options.allow_indented = allow_indented;
+        self
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    #[cfg(feature = "std")]
+    pub async fn build<T>(self, reader: T) -> Result<Parser<T>>
+        where
+            T: AsyncBufReadExt + Unpin,
+    {
+        Parser::with_options(reader, self.options).await
+    }
+}
+
+
+#[cfg(feature = "std")]
 #[pin_project]
-struct ResultStream<T>
+pub struct ResultStream<T>
 {
     #[pin]
     stream: T,
-    buffer: Vec<u8>,
-}
-
-impl<T> ResultStream<T>
-    where T: AsyncReadExt
-{
-    /// Polls the underlying stream for read readiness.
-    fn poll_for_read(stream: &mut Pin<&mut T>, buffer: &mut Vec<u8>, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
-        let mut fut = stream.read_to_end(buffer);
-        let fut = Pin::new(&mut fut);
-        fut.poll(cx)
-    }
+    version: u32,
+    decoder: Decoder,
 }
 
+#[cfg(feature = "std")]
 impl<T> Stream for ResultStream<T>
     where
-        T: AsyncReadExt
+        T: AsyncBufRead
 {
-    type Item = io::Result<TestPoint>;
+    type Item = io::Result<TestDetails>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
 
-        // read from the stream
-        let read = Self::poll_for_read(&mut this.stream, this.buffer, cx);
-
-        // if the stream is not ready, return
-        match read {
-            Poll::Pending => return Poll::Pending,
-            Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
-            Poll::Ready(Ok(0)) => todo!(),
-            Poll::Ready(Ok(_)) => {}
-        }
-
-        // parse the buffer
-        let string = std::str::from_utf8(this.buffer).expect("buffer should be utf8");
-        let test_point = parse_test_points(string);
-
-        match test_point {
-            // we parsed a test point, return it, but keep the rest of the buffer
-            #[allow(unused_variables)]
-            Ok((remaining, test_point)) => {
-                // discard the parsed part of the buffer
-                this.buffer.drain(0..(string.len() - remaining.len()));
-                todo!()
-            }
-            // if we can need more data, keep reading
-            Err(nom::Err::Incomplete(_)) => {
-                Poll::Pending
+        loop {
+            match this.decoder.decode() {
+                Decoded::Detail(detail) => return Poll::Ready(Some(detail)),
+                Decoded::End => return Poll::Ready(None),
+                Decoded::NeedData => {}
             }
-            Err(_) => todo!(),
+
+            // read from the stream
+            let available = match ready!(this.stream.as_mut().poll_fill_buf(cx)) {
+                Ok(available) => available,
+                Err(e) => {
+                    this.decoder.read_failed(e);
+                    continue;
+                }
+            };
+            let read = available.len();
+            this.decoder.feed(available);
+            this.stream.as_mut().consume(read);
         }
     }
 
@@ -117,25 +645,583 @@ impl<T> Stream for ResultStream<T>
     }
 }
 
+#[cfg(feature = "std")]
 impl<T> Parser<T>
     where
         T: AsyncBufReadExt + Unpin,
 {
-    pub async fn new(mut stream: T) -> Result<Parser<T>> {
-        // We only parse tap version 14
-        let mut buffer = String::new();
-        stream.read_line(&mut buffer).await?;
-        let (_remaining, _version) = parse_version(&buffer).unwrap();
+    pub async fn new(stream: T) -> Result<Parser<T>> {
+        Self::with_options(stream, ParserOptions::default()).await
+    }
+
+    pub async fn with_options(mut stream: T, options: ParserOptions) -> Result<Parser<T>> {
+        let mut offset = 0;
+        // a resilient parser never gets to see the version line, but 14 is all we understand anyway
+        let mut version = 14;
+        let mut first_line = Vec::new();
+
+        // some Windows runners start their output with a byte order mark
+        if stream.fill_buf().await?.starts_with(BYTE_ORDER_MARK) {
+            stream.consume_unpin(BYTE_ORDER_MARK.len());
+            offset += BYTE_ORDER_MARK.len();
+        }
+
+        if options.resilient {
+            // we might have started halfway through a line, so it can't even be trusted to be utf8
+            let mut partial = Vec::new();
+            offset += stream.read_until(b'\n', &mut partial).await?;
+        } else {
+            let mut header = Vec::new();
+            offset += stream.read_until(b'\n', &mut header).await?;
+            (version, first_line) = options.header_version(header)?;
+            offset -= first_line.len();
+        }
 
         Ok(Parser {
             stream,
+            version,
+            offset,
+            first_line,
+            options,
         })
     }
 
     pub fn test_results(self) -> ResultStream<T> {
         ResultStream {
             stream: self.stream,
-            buffer: Vec::new(),
+            version: self.version,
+            decoder: Decoder::after_header(self.offset, self.first_line, self.options),
         }
     }
 }
+
+#[cfg(feature = "tokio")]
+impl<T> Parser<tokio_util::compat::Compat<T>>
+    where
+        T: tokio::io::AsyncBufRead + Unpin,
+{
+    /// A parser reading from a tokio reader, bridged to the futures io traits the parser is built
+    /// on. The stream of results is a [`Stream`] as for any other reader.
+    pub async fn from_tokio(reader: T) -> Result<Parser<tokio_util::compat::Compat<T>>> {
+        use tokio_util::compat::TokioAsyncReadCompatExt;
+
+        Parser::new(reader.compat()).await
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::{StreamExt, TryStreamExt};
+    use super::*;
+
+    fn parse(input: &str) -> Vec<TestDetails> {
+        block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().try_collect().await.unwrap()
+        })
+    }
+
+    #[test]
+    fn test_point_outcome() {
+        let skip = Some(TestDirective::Skip(None));
+        let todo = Some(TestDirective::Todo(None));
+        let cases = [
+            (true, None, TestOutcome::Passed),
+            (false, None, TestOutcome::Failed),
+            (true, skip, TestOutcome::Skipped),
+            (false, todo.clone(), TestOutcome::TodoExpectedFail),
+            (true, todo, TestOutcome::TodoUnexpectedPass),
+        ];
+
+        for (status, directive, expected) in cases {
+            let point = TestPoint {
+                status,
+                test_number: None,
+                description: None,
+                directive,
+                yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+            };
+            assert_eq!(point.outcome(), expected, "{point:?}");
+        }
+    }
+
+    #[test]
+    fn todo_failure_is_successful() {
+        let point = TestPoint {
+            status: false,
+            test_number: Some(1),
+            description: None,
+            directive: Some(TestDirective::Todo(Some("not implemented".to_string()))),
+            yaml: None,
+            truncated_yaml: false,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        };
+        assert!(point.is_successful());
+
+        let point = TestPoint { directive: None, ..point };
+        assert!(!point.is_successful());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_extra_metadata() {
+        let point = TestPoint::ok("first").with_number(1);
+        let json = serde_json::to_value(&point).unwrap();
+        assert!(json.get("extra").is_none());
+
+        let point = TestPoint { extra: Some(serde_json::json!({ "run_id": 42 })), ..point };
+        let json = serde_json::to_string(&point).unwrap();
+        assert!(json.contains(r#""extra":{"run_id":42}"#), "{json}");
+        assert_eq!(serde_json::from_str::<TestPoint>(&json).unwrap(), point);
+    }
+
+    #[test]
+    fn stream_plan_label() {
+        let input = "TAP Version 14\n# three tests\n1..3\nok 1\n";
+        let options = ParserOptions { plan_labels: true, ..Default::default() };
+
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::with_options(Cursor::new(input.as_bytes()), options).await.unwrap();
+            parser.test_results().try_collect().await.unwrap()
+        });
+
+        let expected = TestPlan { label: Some("three tests".to_string()), ..TestPlan::new(3) };
+        assert_eq!(details.len(), 2);
+        assert_eq!(details[0], TestDetails::TestPlan(expected));
+    }
+
+    #[test]
+    fn stream_resilient_mid_line() {
+        let input = "ption of a point we missed\nok 2 - next\n";
+        let options = ParserOptions { resilient: true, ..Default::default() };
+
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::with_options(Cursor::new(input.as_bytes()), options).await.unwrap();
+            parser.test_results().try_collect().await.unwrap()
+        });
+
+        let [TestDetails::TestPoint(point)] = &details[..] else {
+            panic!("expected a single test point, got {details:?}");
+        };
+        assert_eq!(point.test_number, Some(2));
+    }
+
+    #[test]
+    fn stream_truncated_yaml() {
+        let details = parse("TAP Version 14\nnot ok 1\n  ---\n  message: cut off\n");
+
+        let [TestDetails::TestPoint(point)] = &details[..] else {
+            panic!("expected a single test point, got {details:?}");
+        };
+        assert!(point.truncated_yaml);
+        assert_eq!(point.yaml.as_deref(), Some("message: cut off\n"));
+    }
+
+    #[test]
+    fn stream_without_trailing_newline() {
+        let details = parse("TAP Version 14\nok 1\nok 2");
+
+        let test_numbers: Vec<_> = details.iter().map(|detail| match detail {
+            TestDetails::TestPoint(point) => point.test_number,
+            _ => panic!("expected only test points, got {detail:?}"),
+        }).collect();
+        assert_eq!(test_numbers, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn builder_strict_rejects_unknown_line() {
+        let input = "TAP Version 14\nok 1 - fine\nthis is not tap\n";
+
+        let details: Vec<_> = block_on(async {
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        assert!(matches!(&details[0], Ok(TestDetails::TestPoint(_))));
+        let Err(err) = &details[1] else {
+            panic!("expected the unknown line to be rejected, got {details:?}");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn builder_expected_version_mismatch() {
+        let input = "TAP Version 14\nok 1\n";
+
+        let result = block_on(ParserBuilder::new().expected_version(Some(13)).build(Cursor::new(input.as_bytes())));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn stream_without_version_line() {
+        let (version, details) = block_on(async {
+            let parser = Parser::new(Cursor::new("1..2\nok 1\nok 2\n".as_bytes())).await.unwrap();
+            let version = parser.version;
+            (version, parser.test_results().try_collect::<Vec<_>>().await.unwrap())
+        });
+
+        let point = |number| TestDetails::TestPoint(TestPoint { description: None, ..TestPoint::ok("").with_number(number) });
+        assert_eq!(version, 12);
+        assert_eq!(details, vec![TestDetails::TestPlan(TestPlan::new(2)), point(1), point(2)]);
+
+        let details = parse("ok 1\nok 2\n");
+        assert_eq!(details.len(), 2);
+    }
+
+    #[test]
+    fn stream_test_number_overflow() {
+        let details = parse_chunks(vec![b"TAP Version 14\nok 99999999999999999999\nok 2\n"]);
+
+        let [Err(err), Ok(TestDetails::TestPoint(point))] = &details[..] else {
+            panic!("expected an error and a test point, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::TestNumberOverflow(number)) if number == "99999999999999999999"));
+        assert_eq!(point.test_number, Some(2));
+    }
+
+    #[test]
+    fn stream_plan_count_overflow() {
+        let details = parse_chunks(vec![b"TAP Version 14\nok 1\n1..99999999999999999999\nok 2\n"]);
+
+        let [Ok(_), Err(err), Ok(TestDetails::TestPoint(point))] = &details[..] else {
+            panic!("expected the error in between the points, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::PlanCountOverflow(count)) if count == "99999999999999999999"));
+        assert_eq!(point.test_number, Some(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn stream_from_tokio() {
+        let reader = tokio::io::BufReader::new(&b"TAP Version 14\n1..2\nok 1\nnot ok 2\n"[..]);
+
+        let (version, details): (_, Vec<_>) = block_on(async {
+            let parser = Parser::from_tokio(reader).await.unwrap();
+            (parser.version, parser.test_results().try_collect().await.unwrap())
+        });
+        assert_eq!(version, 14);
+        assert_eq!(details, vec![
+            TestDetails::TestPlan(TestPlan::new(2)),
+            TestDetails::TestPoint(TestPoint { description: None, ..TestPoint::ok("").with_number(1) }),
+            TestDetails::TestPoint(TestPoint { description: None, ..TestPoint::not_ok("").with_number(2) }),
+        ]);
+    }
+
+    #[test]
+    fn stream_version_overflow() {
+        let result = block_on(Parser::new(Cursor::new("TAP version 99999999999\nok 1\n".as_bytes())));
+        let Err(err) = result else {
+            panic!("expected the version to be rejected");
+        };
+        assert!(matches!(ParseError::from(err), ParseError::VersionOverflow(_)));
+    }
+
+    #[test]
+    fn builder_attach_diagnostics() {
+        let input = "TAP Version 14\nok 1 - first\n# took a while\n# Subtest: nested\nok 2\n";
+
+        let details: Vec<_> = block_on(async {
+            let parser = ParserBuilder::new().attach_diagnostics(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().try_collect().await.unwrap()
+        });
+
+        let [TestDetails::TestPoint(point), TestDetails::Comment(marker), TestDetails::TestPoint(_)] = &details[..] else {
+            panic!("expected the comment to be attached, got {details:?}");
+        };
+        assert_eq!(point.comments, vec!["took a while".to_string()]);
+        assert_eq!(marker, &Comment("Subtest: nested".into()));
+        assert!(marker.is_diagnostic());
+    }
+
+    #[test]
+    fn test_point_from_str() {
+        let point: TestPoint = "ok 1 - it works".parse().unwrap();
+        assert!(point.status);
+        assert_eq!(point.test_number, Some(1));
+        assert_eq!(point.description.as_deref(), Some("it works"));
+    }
+
+    #[test]
+    fn test_point_from_str_leftover() {
+        let err = "ok 1 - it works\nnot a point".parse::<TestPoint>().unwrap_err();
+        assert!(matches!(err, ParseError::Malformed(line) if line == "not a point"));
+    }
+
+    #[test]
+    fn test_point_from_str_empty() {
+        assert!("".parse::<TestPoint>().is_err());
+    }
+
+    #[test]
+    fn test_point_eq_ignoring_diagnostics() {
+        let plain = TestPoint::not_ok("broken").with_number(2);
+        let mut diagnosed = plain.clone().with_yaml("got: 1\n".to_string());
+        diagnosed.comments.push("retried".to_string());
+
+        assert_ne!(plain, diagnosed);
+        assert!(plain.eq_ignoring_diagnostics(&diagnosed));
+        assert!(!plain.eq_ignoring_diagnostics(&TestPoint::ok("broken").with_number(2)));
+    }
+
+    #[test]
+    fn test_point_order() {
+        let mut points = [
+            TestPoint::ok("unnumbered"),
+            TestPoint::ok("b").with_number(3),
+            TestPoint::not_ok("first").with_number(1),
+            TestPoint::ok("a").with_number(3),
+            TestPoint::ok("another"),
+        ];
+        points.sort();
+
+        let order: Vec<_> = points.iter().map(|point| (point.test_number, point.description.as_deref().unwrap())).collect();
+        assert_eq!(order, vec![
+            (Some(1), "first"),
+            (Some(3), "a"),
+            (Some(3), "b"),
+            (None, "another"),
+            (None, "unnumbered"),
+        ]);
+    }
+
+    #[test]
+    fn test_point_constructors() {
+        let point = TestPoint::not_ok("boom").with_number(3).with_directive(TestDirective::Todo(None));
+
+        assert_eq!(point, TestPoint {
+            status: false,
+            test_number: Some(3),
+            description: Some("boom".to_string()),
+            directive: Some(TestDirective::Todo(None)),
+            yaml: None,
+            truncated_yaml: false,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        });
+        assert_eq!(point.to_string(), "not ok 3 - boom # TODO");
+
+        let point = TestPoint::ok("fine").with_yaml("got: 1\n".to_string());
+        assert_eq!(point.to_string(), "ok - fine\n  ---\n  got: 1\n  ...");
+    }
+
+    fn parse_chunks(chunks: Vec<&'static [u8]>) -> Vec<io::Result<TestDetails>> {
+        let reader = futures::stream::iter(chunks.into_iter().map(Ok)).into_async_read();
+
+        block_on(async {
+            let parser = Parser::new(reader).await.unwrap();
+            parser.test_results().collect().await
+        })
+    }
+
+    #[test]
+    fn stream_utf8_split_across_reads() {
+        let details = parse_chunks(vec![b"TAP Version 14\nok 1 - caf\xc3", b"\xa9\n"]);
+
+        let [Ok(TestDetails::TestPoint(point))] = &details[..] else {
+            panic!("expected a single test point, got {details:?}");
+        };
+        assert_eq!(point.description.as_deref(), Some("caf\u{e9}"));
+    }
+
+    #[test]
+    fn stream_invalid_utf8() {
+        let details = parse_chunks(vec![b"TAP Version 14\nok 1 - \xff\xfe\n"]);
+
+        let [Err(err)] = &details[..] else {
+            panic!("expected a single error, got {details:?}");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stream_invalid_utf8_after_valid_lines() {
+        let details = parse_chunks(vec![b"TAP Version 14\nok 1 - fine\nok 2 - \xff\n"]);
+
+        let [Ok(TestDetails::TestPoint(point)), Err(err)] = &details[..] else {
+            panic!("expected a test point and an error, got {details:?}");
+        };
+        assert_eq!(point.description.as_deref(), Some("fine"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stream_byte_order_mark() {
+        let details = parse("\u{FEFF}TAP Version 14\nok 1\n");
+
+        let [TestDetails::TestPoint(point)] = &details[..] else {
+            panic!("expected a single test point, got {details:?}");
+        };
+        assert_eq!(point.test_number, Some(1));
+    }
+
+    #[test]
+    fn stream_unparsed_line() {
+        let input = "TAP Version 14\nok 1\n1..two\nok 2\n";
+
+        let details: Vec<_> = block_on(async {
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(_), Err(err), Ok(TestDetails::TestPoint(point))] = &details[..] else {
+            panic!("expected the plan to be reported in between the points, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(
+            error,
+            Some(ParseError::Unparsed { line, offset: 20 }) if line == "1..two"
+        ));
+        assert_eq!(point.test_number, Some(2));
+    }
+
+    #[test]
+    fn stream_truncated_last_line_at_eof() {
+        // the subtest is never closed and the last line is cut off, only the end of the input
+        // settles what they are
+        let input = "TAP Version 14\nok 1\n    ok 1 - nested\nnot o";
+
+        let details: Vec<_> = block_on(async {
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(TestDetails::TestPoint(_)), Ok(TestDetails::Subtest(subtest)), Err(err)] = &details[..] else {
+            panic!("expected the open subtest and the cut off line to be reported, got {details:?}");
+        };
+        assert_eq!(subtest.details.len(), 1);
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::Unparsed { line, .. }) if line == "not o"), "{err:?}");
+    }
+
+    #[test]
+    fn stream_duplicate_plan() {
+        let input = "TAP Version 14\n1..2\nok 1\nok 2\n1..2\n";
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(TestDetails::TestPlan(_)), Ok(_), Ok(_), Err(err)] = &details[..] else {
+            panic!("expected the second plan to be rejected, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::DuplicatePlan(_))), "{err:?}");
+
+        let err = block_on(crate::parse_all(Cursor::new(input.as_bytes()))).unwrap_err();
+        assert!(matches!(err, ParseError::Interrupted { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn stream_tests_after_empty_plan() {
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new("TAP Version 14\n1..0\nok 1\n".as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(TestDetails::TestPlan(_)), Err(err), Ok(TestDetails::TestPoint(_))] = &details[..] else {
+            panic!("expected the point after the empty plan to be reported, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::TestsAfterEmptyPlan)), "{err:?}");
+
+        // skipping the run only makes them extra
+        let details = parse("TAP Version 14\n1..0 # SKIP no device\nok 1\n");
+        assert_eq!(details[1], TestDetails::ExtraTest { planned: 0, got: 1 });
+    }
+
+    #[test]
+    fn stream_read_error_context() {
+        let chunks: Vec<io::Result<&[u8]>> = vec![
+            Ok(b"TAP Version 14\nok 1\nok 2\n"),
+            Err(io::Error::other("connection lost")),
+        ];
+        let reader = futures::stream::iter(chunks).into_async_read();
+
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::new(reader).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(TestDetails::TestPoint(_)), Ok(TestDetails::TestPoint(_)), Err(err)] = &details[..] else {
+            panic!("expected two points and the read error, got {details:?}");
+        };
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        let Some(ParseError::Io { line, points, .. }) = error else {
+            panic!("expected the read error with context, got {err:?}");
+        };
+        assert_eq!((*line, *points), (Some(4), 2));
+
+        let source = std::error::Error::source(error.unwrap()).unwrap();
+        assert_eq!(source.to_string(), "connection lost");
+    }
+
+    #[test]
+    fn stream_strict_pragma() {
+        let input = "TAP Version 14\nwhat\npragma +strict\nok 1\nhuh\npragma -strict\nfine\nok 2\n";
+        let details: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect().await
+        });
+
+        let [Ok(TestDetails::Anything(before)), Ok(TestDetails::Pragma(_)), Ok(TestDetails::TestPoint(_)), Err(err), Ok(TestDetails::Pragma(_)), Ok(TestDetails::Anything(after)), Ok(TestDetails::TestPoint(_))] = &details[..] else {
+            panic!("expected only the line under the strict pragma to fail, got {details:?}");
+        };
+        assert_eq!((before.as_str(), after.as_str()), ("what", "fine"));
+        let error = err.get_ref().and_then(|error| error.downcast_ref::<ParseError>());
+        assert!(matches!(error, Some(ParseError::Unparsed { line, .. }) if line == "huh"), "{err:?}");
+    }
+
+    #[test]
+    fn stream_resync_after_malformed_line() {
+        let details = parse("TAP Version 14\nok 1\n1..two\nok 2\n");
+
+        let [TestDetails::TestPoint(first), TestDetails::Anything(line), TestDetails::TestPoint(second)] = &details[..] else {
+            panic!("expected the malformed line in between the points, got {details:?}");
+        };
+        assert_eq!(line, "1..two");
+        assert_eq!((first.test_number, second.test_number), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn stream_warns_about_extra_test() {
+        let details = parse("TAP Version 14\n1..2\nok 1\nok 2\nok 3\n");
+
+        assert_eq!(details.len(), 5);
+        assert_eq!(details[3], TestDetails::ExtraTest { planned: 2, got: 3 });
+        assert!(matches!(&details[4], TestDetails::TestPoint(point) if point.test_number == Some(3)));
+
+        block_on(async {
+            let input = "TAP Version 14\n1..2\nok 1\nok 2\nok 3\n";
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            let error: ParseError = parser.test_results().skip(3).next().await.unwrap().unwrap_err().into();
+            assert!(matches!(error, ParseError::ExtraTest { planned: 2, got: 3 }), "{error:?}");
+        });
+    }
+
+    #[test]
+    fn stream_skip_all_plan() {
+        let details = parse("TAP Version 14\n1..0 # Skipped: no device\n");
+
+        assert_eq!(details, vec![TestDetails::TestPlan(TestPlan {
+            start: 1,
+            end: 0,
+            label: None,
+            reason: Some("Skipped: no device".to_string()),
+            directive: None,
+        })]);
+
+        let details = parse("TAP Version 14\n1..2 # two to go\nok 1\nok 2\n");
+        assert!(matches!(&details[..], [TestDetails::TestPlan(_), TestDetails::TestPoint(_), TestDetails::TestPoint(_)]), "{details:?}");
+    }
+}
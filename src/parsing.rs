@@ -3,47 +3,98 @@ use nom::bytes::complete::{tag, tag_no_case};
 use nom::bytes::complete::take_while;
 use nom::bytes::complete::take_until;
 use nom::character::is_alphanumeric;
-use nom::character::complete::{newline, space1};
-use nom::character::complete::{digit1, multispace0, space0};
-use nom::combinator::{opt, rest};
+use nom::character::complete::{line_ending, newline, satisfy, space1};
+use nom::character::complete::{digit1, space0};
+use nom::combinator::{eof, map, not, opt, recognize, rest, value};
+use nom::error::{Error, ErrorKind};
 use nom::IResult;
-use nom::multi::many1;
-use nom::sequence::{delimited, preceded, terminated, tuple};
-use crate::{Pragma, TestDirective, TestPoint};
+use nom::multi::{many0, many1, many_till};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
+use alloc::{borrow::Cow, format, string::{String, ToString}, vec::Vec};
+use core::ops::Range;
+use crate::{BailOut, Comment, ParseError, ParserOptions, Pragma, Subtest, TestDetails, TestDirective, TestPlan, TestPoint, TestPointRef};
 
-pub fn parse_version(s: &str) -> IResult<&str, &str> {
-    tag("TAP Version 14\n")(s)
+/// Numbers too large to count fail with a kind of their own, so that the error can tell which one
+/// it was.
+pub(crate) const PLAN_COUNT_TOO_LARGE: ErrorKind = ErrorKind::TooLarge;
+pub(crate) const TEST_NUMBER_TOO_LARGE: ErrorKind = ErrorKind::Digit;
+pub(crate) const VERSION_TOO_LARGE: ErrorKind = ErrorKind::Verify;
+
+/// Whether a parser failed on a number too large to count, rather than on input it doesn't know.
+pub(crate) fn is_overflow(err: &nom::Err<Error<&str>>) -> bool {
+    matches!(err, nom::Err::Failure(err) if [PLAN_COUNT_TOO_LARGE, TEST_NUMBER_TOO_LARGE, VERSION_TOO_LARGE].contains(&err.code))
 }
 
-fn parse_test_count(s: &str) -> IResult<&str, &str> {
-    preceded(tag("1.."), digit1)(s)
+pub fn parse_version(s: &str) -> IResult<&str, u32> {
+    let (remaining, version) = delimited(
+        pair(tag_no_case("TAP version"), space1),
+        digit1,
+        pair(space0, line_ending),
+    )(s)?;
+
+    let version = version
+        .parse()
+        .map_err(|_| nom::Err::Failure(Error::new(version, VERSION_TOO_LARGE)))?;
+    Ok((remaining, version))
 }
 
-fn parse_plan(s: &str) -> IResult<&str, u32> {
-    fn parse_reason(s: &str) -> IResult<&str, &str> {
-        use nom::bytes::streaming::take_until1;
+fn parse_test_range(s: &str) -> IResult<&str, (usize, usize)> {
+    fn parse_number(s: &str) -> IResult<&str, usize> {
+        let (remaining, number) = digit1(s)?;
 
-        preceded(tag(" # "), take_until1("\n"))(s)
+        // digit1 guarantees a number, so the only way this fails is if it doesn't fit in a usize
+        let number = number
+            .parse()
+            .map_err(|_| nom::Err::Failure(Error::new(number, PLAN_COUNT_TOO_LARGE)))?;
+        Ok((remaining, number))
     }
 
-    fn parse_remaining(s: &str) -> IResult<&str, &str> {
-        alt((parse_reason, tag("\n")))(s)
+    separated_pair(parse_number, tag(".."), parse_number)(s)
+}
+
+/// Parses a plan line, including a directive like `1..0 # SKIP reason` or the free text reason
+/// given for skipping a whole run with `1..0 # reason`.
+fn parse_plan(s: &str) -> IResult<&str, TestPlan> {
+    fn parse_directive(s: &str) -> IResult<&str, TestDirective> {
+        // only the bare keyword counts, `# Skipped: reason` is a reason like any other
+        let (remaining, (keyword, reason)) = delimited(
+            tag(" # "),
+            pair(alt((tag_no_case("skip"), tag_no_case("todo"))), opt(preceded(space1, take_until("\n")))),
+            newline,
+        )(s)?;
+
+        let reason = reason.map(str::trim).filter(|reason| !reason.is_empty()).map(str::to_string);
+        if keyword.eq_ignore_ascii_case("skip") {
+            Ok((remaining, TestDirective::Skip(reason)))
+        } else {
+            Ok((remaining, TestDirective::Todo(reason)))
+        }
     }
 
-    let (remaining, count) = parse_test_count(s)?;
+    fn parse_reason(s: &str) -> IResult<&str, &str> {
+        use nom::bytes::streaming::take_until1;
 
-    let count: u32 = count
-        .parse()
-        .expect("Parsing should guaranteed test count to be a number");
-    Ok((remaining, count))
+        preceded(tag(" # "), terminated(take_until1("\n"), newline))(s)
+    }
+
+    let (remaining, (start, end)) = parse_test_range(s)?;
+    let (remaining, (directive, reason)) = alt((
+        map(parse_directive, |directive| (Some(directive), None)),
+        map(parse_reason, |reason| (None, Some(reason.trim().to_string()))),
+        value((None, None), newline),
+    ))(remaining)?;
+
+    Ok((remaining, TestPlan { start, end, label: None, reason, directive }))
 }
 
-fn parse_bail_out(s: &str) -> IResult<&str, Option<&str>> {
+/// Parses the reason given after `Bail out!`, `None` if there is none.
+fn parse_bail_out_reason(s: &str) -> IResult<&str, Option<&str>> {
     fn parse(s: &str) -> IResult<&str, &str> {
         preceded(tag("Bail out!"), take_until("\n"))(s)
     }
     let (remaining, reason) = terminated(parse, tag("\n"))(s)?;
 
+    let reason = reason.trim();
     if reason.is_empty() {
         Ok((remaining, None))
     } else {
@@ -51,8 +102,52 @@ fn parse_bail_out(s: &str) -> IResult<&str, Option<&str>> {
     }
 }
 
-fn parse_yaml(s: &str) -> IResult<&str, &str> {
-    delimited(tag("  ---\n"), rest, tag("  ...\n"))(s)
+fn parse_bail_out(s: &str) -> IResult<&str, BailOut> {
+    map(parse_bail_out_reason, |reason| BailOut(reason.map(str::to_string)))(s)
+}
+
+/// Parses the body of a yaml block belonging to a test point nested `level` subtests deep, as it
+/// appears in the input. Blocks stacked right after each other all belong to the point, their
+/// body spans all of them, including the `...` and `---` lines in between.
+fn parse_yaml_body(s: &str, level: usize) -> IResult<&str, &str> {
+    // streaming, so a block that hasn't been closed yet asks for more input instead of failing
+    use nom::bytes::streaming::tag as streaming_tag;
+    use nom::character::streaming::{newline, not_line_ending, space0};
+
+    let indent = yaml_indentation(level);
+    let start = format!("{indent}---\n");
+    let end = format!("{indent}...");
+
+    let line = || terminated(not_line_ending, newline);
+    let terminator = || terminated(preceded(streaming_tag(&*end), space0), newline);
+    let block = || many0(preceded(not(terminator()), line()));
+    let body = recognize(pair(block(), many0(tuple((terminator(), tag(&*start), block())))));
+
+    let (remaining, body) = delimited(tag(&*start), body, terminator())(s)?;
+    Ok((remaining, body))
+}
+
+/// Parses a yaml block belonging to a test point nested `level` subtests deep, each level indented
+/// by four spaces. The block's base indentation is stripped, so the result is valid yaml on its
+/// own.
+///
+/// The parsers for whole points and documents dedent subtests before parsing them, so they always
+/// parse their yaml at level 0. This is for input that is still indented.
+pub fn parse_yaml(s: &str, level: usize) -> IResult<&str, String> {
+    let (remaining, body) = parse_yaml_body(s, level)?;
+    Ok((remaining, strip_indentation(body, &yaml_indentation(level))))
+}
+
+fn yaml_indentation(level: usize) -> String {
+    // yaml blocks are indented by two spaces past the test point they belong to
+    " ".repeat(2 + level * 4)
+}
+
+/// Strips the base indentation from each line of a yaml block.
+pub(crate) fn strip_indentation(body: &str, indent: &str) -> String {
+    body.split_inclusive('\n')
+        .map(|line| line.strip_prefix(indent).unwrap_or_else(|| line.trim_start_matches(' ')))
+        .collect()
 }
 
 fn parse_comment(s: &str) -> IResult<&str, Option<&str>> {
@@ -69,8 +164,8 @@ fn parse_comment(s: &str) -> IResult<&str, Option<&str>> {
     }
 }
 
-fn parse_empty(s: &str) -> IResult<&str, &str> {
-    preceded(multispace0, tag("\n"))(s)
+fn parse_empty(s: &str) -> IResult<&str, char> {
+    preceded(space0, newline)(s)
 }
 
 fn parse_anything(s: &str) -> IResult<&str, &str> {
@@ -99,29 +194,50 @@ fn parse_pragma(s: &str) -> IResult<&str, Pragma> {
     Ok((remaining, pragma))
 }
 
-fn parse_description(s: &str) -> IResult<&str, String> {
-    use nom::bytes::complete::take_until1;
+/// Parses the description of a test point, `None` if it's only whitespace. A `#` trailer that isn't
+/// a TODO or SKIP directive, like `# note: retried`, is part of the description.
+fn parse_description(s: &str) -> IResult<&str, Option<&str>> {
+    use nom::bytes::complete::take_till1;
 
     let prefix = tag(" -");
-    // ordering between " #" and "\n" is important, because " #" denotes the start of directives, we
-    // want to match that first before trying to match the newline.
-    let description = preceded(space1, alt((take_until1(" #"), take_until1("\n"))));
+    let (line, _prefix) = opt(prefix)(s)?;
+    let (_, until_newline) = take_till1(|c| c == '\n')(line)?;
 
-    let (remaining, description) = preceded(opt(prefix), description)(s)?;
-    Ok((remaining, description.trim().to_string()))
+    // " #" denotes the start of a directive, but descriptions may well contain " #" themselves, so
+    // only split where an actual directive follows.
+    let end = until_newline
+        .match_indices(" #")
+        .map(|(index, _)| index)
+        .find(|&index| parse_directive(&line[index..]).is_ok())
+        .unwrap_or(until_newline.len());
+    let (_, description) = preceded(space1, rest)(&line[..end])?;
+
+    let description = description.trim();
+    if description.is_empty() {
+        Ok((&line[end..], None))
+    } else {
+        Ok((&line[end..], Some(description)))
+    }
 }
 
+/// Parses a `# TODO` or `# SKIP` directive, also accepting the legacy `SKIPPED` and a colon after
+/// the keyword.
 fn parse_directive(s: &str) -> IResult<&str, TestDirective> {
     let (remaining, _prefix) = tag(" #")(s)?;
     let (remaining, _prefix) = space0(remaining)?;
-    let (remaining, directive) = alt((tag_no_case("todo"), tag_no_case("skip")))(remaining)?;
-    let (remaining, _) = take_while(|c: char| { !c.is_whitespace() })(remaining)?;
+    let (remaining, directive) = alt((
+        value("todo", tag_no_case("todo")),
+        value("skip", tag_no_case("skipped")),
+        value("skip", tag_no_case("skip")),
+    ))(remaining)?;
+    let (remaining, _colon) = opt(tag(":"))(remaining)?;
+    // the keyword has to be a word of its own, `# skipping ahead` is just a comment
+    let (remaining, _) = not(satisfy(|c| !c.is_whitespace()))(remaining)?;
     let (remaining, reason) = preceded(space0, take_until("\n"))(remaining)?;
 
-    let directive = directive.to_lowercase();
     let reason = reason.trim();
     let reason = if reason.is_empty() { None } else { Some(reason.to_string()) };
-    match &*directive {
+    match directive {
         "todo" => Ok((remaining, TestDirective::Todo(reason))),
         "skip" => Ok((remaining, TestDirective::Skip(reason))),
         _ => unreachable!(),
@@ -139,44 +255,359 @@ fn parse_status(s: &str) -> IResult<&str, bool> {
 }
 
 fn parse_test_number(s: &str) -> IResult<&str, usize> {
-    let (remaining, num) = preceded(space1, digit1)(s)?;
-    Ok((remaining, num.parse().expect("Test number should be a number")))
+    let (remaining, digits) = preceded(space1, digit1)(s)?;
+    Ok((remaining, test_number(digits)?))
 }
 
-fn parse_test_point(s: &str) -> IResult<&str, TestPoint> {
-    fn parse(s: &str) -> IResult<&str, (bool, Option<usize>, Option<String>, Option<TestDirective>, char, Option<&str>)> {
-        tuple((
-            parse_status,
-            opt(parse_test_number),
-            opt(parse_description),
-            opt(parse_directive),
-            newline,
-            opt(parse_yaml),
-        ))(s)
-    }
+/// Parses the line of a test point, without any yaml block that might follow it.
+fn parse_test_point_line(s: &str) -> IResult<&str, TestPointRef<'_>> {
+    let (remaining, (status, test_number)) = pair(parse_status, opt(parse_test_number))(s)?;
+    parse_test_point_line_end(remaining, status, test_number)
+}
 
-    let (remaining, (status, test_number, description, directive, _newline, yaml)) = parse(s)?;
-    let yaml = yaml.map(|yaml| {
-        yaml.to_string()
-    });
+/// Parses the rest of a test point line, following its status and number.
+fn parse_test_point_line_end(s: &str, status: bool, test_number: Option<usize>) -> IResult<&str, TestPointRef<'_>> {
+    let (remaining, (description, directive, _newline)) = tuple((
+        map(opt(parse_description), Option::flatten),
+        opt(parse_directive),
+        newline,
+    ))(s)?;
 
-    Ok((remaining, TestPoint {
+    Ok((remaining, TestPointRef {
         status,
         description,
         directive,
-        yaml,
+        yaml: None,
+        truncated_yaml: false,
         test_number,
     }))
 }
 
+/// Parses a test point without copying anything out of the input. Only a yaml block starting on
+/// the very next line belongs to the point, anything in between breaks the association.
+pub fn parse_test_point_ref(s: &str) -> IResult<&str, TestPointRef<'_>> {
+    let (remaining, point) = parse_test_point_line(s)?;
+    let (remaining, yaml) = opt(|s| parse_yaml_body(s, 0))(remaining)?;
+
+    Ok((remaining, TestPointRef { yaml, ..point }))
+}
+
+pub(crate) fn parse_test_point(s: &str) -> IResult<&str, TestPoint> {
+    map(parse_test_point_ref, |point| point.to_owned())(s)
+}
+
+/// Parses a test point, together with the comment lines following it if they are to be attached.
+fn parse_test_point_with<'a>(s: &'a str, options: &ParserOptions) -> IResult<&'a str, TestPoint> {
+    let (remaining, point) = if options.strict {
+        parse_test_point(s)?
+    } else {
+        alt((parse_test_point, parse_unspaced_test_point))(s)?
+    };
+    if !options.attach_diagnostics {
+        return Ok((remaining, point));
+    }
+
+    let (remaining, comments) = many0(parse_attached_comment)(remaining)?;
+    Ok((remaining, TestPoint { comments, ..point }))
+}
+
+/// Turns the digits of a test number into the number, failing if it doesn't fit in a usize.
+fn test_number(digits: &str) -> Result<usize, nom::Err<Error<&str>>> {
+    digits.parse().map_err(|_| nom::Err::Failure(Error::new(digits, TEST_NUMBER_TOO_LARGE)))
+}
+
+/// Parses a test point missing the space between its status and number, like `ok1`. It isn't
+/// valid TAP, but common enough to be worth understanding when parsing leniently.
+fn parse_unspaced_test_point(s: &str) -> IResult<&str, TestPoint> {
+    let (remaining, (status, number)) = pair(parse_status, digit1)(s)?;
+    let test_number = test_number(number)?;
+
+    let (remaining, point) = parse_test_point_line_end(remaining, status, Some(test_number))?;
+    let (remaining, yaml) = opt(|s| parse_yaml_body(s, 0))(remaining)?;
+
+    Ok((remaining, TestPointRef { yaml, ..point }.to_owned()))
+}
+
+/// Parses an unindented comment line that can be attached to the preceding test point. Subtest
+/// markers belong to what follows them, so they are left alone.
+fn parse_attached_comment(s: &str) -> IResult<&str, String> {
+    let (remaining, comment) = preceded(tag("#"), terminated(take_until("\n"), newline))(s)?;
+    if comment.trim_start().starts_with("Subtest") {
+        return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+    }
+
+    Ok((remaining, comment.trim().to_string()))
+}
+
+/// Parses a test point whose yaml block is cut off by the end of the input, e.g. because the
+/// producer crashed halfway through its diagnostics. Whatever made it into the block is kept.
+fn parse_truncated_test_point(s: &str) -> IResult<&str, TestPoint> {
+    let (remaining, point) = parse_test_point_line(s)?;
+    let (body, _start) = tag("  ---\n")(remaining)?;
+
+    let point = TestPointRef { yaml: Some(body), truncated_yaml: true, ..point };
+    Ok(("", point.to_owned()))
+}
+
 pub fn parse_test_points(s: &str) -> IResult<&str, Vec<TestPoint>> {
     many1(parse_test_point)(s)
 }
 
+/// Parses a run of test points like [`parse_test_points`], but borrowing their descriptions and
+/// yaml from the input instead of copying each of them into a `String` of its own.
+pub fn parse_test_point_refs(s: &str) -> IResult<&str, Vec<TestPointRef<'_>>> {
+    many1(parse_test_point_ref)(s)
+}
+
+fn parse_subtest<'a>(s: &'a str, options: &ParserOptions) -> IResult<&'a str, Subtest> {
+    let indent = options.subtest_indent.to_string();
+    if indent.is_empty() {
+        // every line would be a subtest of itself
+        return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+    }
+    // everything after one level of indentation is a document of its own
+    let parse_indented_line = preceded(tag(&*indent), recognize(terminated(take_until("\n"), newline)));
+
+    let (remaining, name) = opt(parse_subtest_marker)(s)?;
+    let (remaining, lines) = many1(parse_indented_line)(remaining)?;
+    let body = lines.concat();
+    // the body is a fresh allocation, so errors need to point back into the original input
+    let (_, details) = parse_details(&body, options).map_err(|e| e.map(|e| Error::new(s, e.code)))?;
+
+    let name = name.map(str::to_string);
+    Ok((remaining, Subtest { name, details }))
+}
+
+/// Parses the `# Subtest: name` comment announcing a subtest, returns its name.
+fn parse_subtest_marker(s: &str) -> IResult<&str, &str> {
+    let (remaining, name) = preceded(tag("# Subtest:"), terminated(take_until("\n"), newline))(s)?;
+    Ok((remaining, name.trim()))
+}
+
+fn parse_labeled_plan(s: &str) -> IResult<&str, TestPlan> {
+    let (remaining, (label, plan)) = tuple((
+        terminated(parse_comment, newline),
+        parse_plan,
+    ))(s)?;
+
+    let label = label.map(|label| label.trim().to_string());
+    Ok((remaining, TestPlan { label, ..plan }))
+}
+
+pub fn parse_detail<'a>(s: &'a str, options: &ParserOptions) -> IResult<&'a str, TestDetails> {
+    if options.plan_labels {
+        if let Ok((remaining, plan)) = parse_labeled_plan(s) {
+            return Ok((remaining, TestDetails::TestPlan(plan)));
+        }
+    }
+
+    alt((
+        map(|s| parse_subtest(s, options), TestDetails::Subtest),
+        map(parse_bail_out, TestDetails::BailOut),
+        map(parse_plan, TestDetails::TestPlan),
+        map(terminated(parse_pragma, newline), TestDetails::Pragma),
+        map(|s| parse_test_point_with(s, options), TestDetails::TestPoint),
+        map(terminated(parse_comment, newline), |comment| {
+            TestDetails::Comment(Comment(comment.unwrap_or_default().trim().to_string()))
+        }),
+        map(parse_empty, |_| TestDetails::Empty),
+        map(terminated(parse_anything, newline), |line| TestDetails::Anything(line.to_string())),
+    ))(s)
+}
+
+/// Parses a detail knowing no more input will follow, so anything still waiting for more is as
+/// complete as it will ever be.
+pub fn parse_detail_at_eof<'a>(s: &'a str, options: &ParserOptions) -> IResult<&'a str, TestDetails> {
+    match parse_detail(s, options) {
+        Err(nom::Err::Incomplete(_)) => map(parse_truncated_test_point, TestDetails::TestPoint)(s),
+        result => result,
+    }
+}
+
+/// Parses all details of a complete input.
+pub fn parse_details<'a>(s: &'a str, options: &ParserOptions) -> IResult<&'a str, Vec<TestDetails>> {
+    let (remaining, (details, _eof)) = many_till(|s| parse_detail_at_eof(s, options), eof)(s)?;
+    Ok((remaining, details))
+}
+
+/// Parses a whole document (without the version line) into its details.
+pub fn parse_document(s: &str) -> Result<Vec<TestDetails>, ParseError> {
+    parse_document_with(s, &ParserOptions::default())
+}
+
+pub fn parse_document_with(s: &str, options: &ParserOptions) -> Result<Vec<TestDetails>, ParseError> {
+    let s = terminate_last_line(s);
+    let (_remaining, details) = parse_details(&s, options)?;
+
+    check_single_plan(&details)?;
+    #[cfg(feature = "tracing")]
+    for detail in &details {
+        let _entered = crate::trace::span().entered();
+        crate::trace::detail(detail);
+    }
+
+    Ok(details)
+}
+
+/// Like [`parse_document`], with the byte range of the input each detail took up. The range of a
+/// test point covers its yaml block, the range of a subtest all of its lines.
+pub fn parse_document_spanned(s: &str) -> Result<Vec<(Range<usize>, TestDetails)>, ParseError> {
+    let options = ParserOptions::default();
+    let terminated = terminate_last_line(s);
+
+    let mut spanned = Vec::new();
+    let mut remaining: &str = &terminated;
+    while !remaining.is_empty() {
+        let start = terminated.len() - remaining.len();
+        let (rest, detail) = parse_detail_at_eof(remaining, &options)?;
+        if rest.len() == remaining.len() {
+            let line = remaining.lines().next().unwrap_or_default();
+            return Err(ParseError::Malformed(line.to_string()));
+        }
+        remaining = rest;
+
+        // the newline added to the last line isn't part of the input
+        let end = (terminated.len() - remaining.len()).min(s.len());
+        spanned.push((start..end, detail));
+    }
+
+    check_single_plan(spanned.iter().map(|(_, detail)| detail))?;
+
+    Ok(spanned)
+}
+
+/// The end of the document terminates the last line, even if the producer didn't.
+fn terminate_last_line(s: &str) -> Cow<'_, str> {
+    if s.is_empty() || s.ends_with('\n') {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("{s}\n"))
+    }
+}
+
+/// A run has at most one plan, either before or after its test points.
+fn check_single_plan<'a>(details: impl IntoIterator<Item = &'a TestDetails>) -> Result<(), ParseError> {
+    let mut plans = details.into_iter().filter_map(|detail| match detail {
+        TestDetails::TestPlan(plan) => Some(plan),
+        _ => None,
+    });
+    if let (Some(_), Some(duplicate)) = (plans.next(), plans.next()) {
+        return Err(ParseError::DuplicatePlan(duplicate.clone()));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
+    use alloc::vec;
     use super::*;
 
+    #[test]
+    fn bail_out() {
+        assert_eq!(parse_bail_out("Bail out!\n"), Ok(("", BailOut(None))));
+        assert_eq!(parse_bail_out("Bail out!   \n"), Ok(("", BailOut(None))));
+        assert_eq!(parse_bail_out("Bail out! database down\n"), Ok(("", BailOut(Some("database down".to_string())))));
+    }
+
+    #[test]
+    fn document_spans() {
+        let input = "1..2\nnot ok 1 - first\n  ---\n  got: 1\n  ...\nok 2 - second";
+        let spanned = parse_document_spanned(input).unwrap();
+
+        let spans: Vec<_> = spanned.iter().map(|(span, _)| span.clone()).collect();
+        assert_eq!(spans, vec![0..5, 5..43, 43..56]);
+        // the first point's span covers its yaml block, the second starts right after it
+        assert_eq!(&input[spans[1].clone()], "not ok 1 - first\n  ---\n  got: 1\n  ...\n");
+        assert_eq!(&input[spans[2].clone()], "ok 2 - second");
+        assert!(matches!(&spanned[2].1, TestDetails::TestPoint(point) if point.test_number == Some(2)));
+    }
+
+    #[test]
+    fn version() {
+        assert_eq!(parse_version("TAP Version 14\n"), Ok(("", 14)));
+        assert_eq!(parse_version("tap version 13\n"), Ok(("", 13)));
+        assert_eq!(parse_version("TAP version 14 \nok\n"), Ok(("ok\n", 14)));
+        assert!(parse_version("TAP version\n").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let input = "1..10\n";
+
+        let (remaining, plan) = parse_plan(input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(plan, TestPlan::new(10));
+    }
+
+    #[test]
+    fn test_plan_skip_reason() {
+        let input = "1..0 # Skipped: reason\n";
+
+        let (remaining, plan) = parse_plan(input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(plan, TestPlan { reason: Some("Skipped: reason".to_string()), ..TestPlan::new(0) });
+    }
+
+    #[test]
+    fn test_plan_directive() {
+        let input = "1..0 # SKIP no hardware\n";
+
+        let (remaining, plan) = parse_plan(input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(plan.directive, Some(TestDirective::Skip(Some("no hardware".to_string()))));
+        assert_eq!(plan.reason, None);
+    }
+
+    #[test]
+    fn test_plan_todo_and_skip() {
+        let (_remaining, todo) = parse_plan("1..0 # TODO not written yet\n").unwrap();
+        assert_eq!(todo.directive, Some(TestDirective::Todo(Some("not written yet".to_string()))));
+        assert!(todo.is_todo() && !todo.is_skip());
+
+        let (_remaining, skip) = parse_plan("1..0 # SKIP no network\n").unwrap();
+        assert_eq!(skip.directive, Some(TestDirective::Skip(Some("no network".to_string()))));
+        assert!(skip.is_skip() && !skip.is_todo());
+    }
+
+    #[test]
+    fn test_plan_range() {
+        let input = "5..9\n";
+
+        let (remaining, plan) = parse_plan(input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!((plan.start, plan.end), (5, 9));
+        assert_eq!(plan.count(), 5);
+    }
+
+    #[test]
+    fn test_plan_count_overflow() {
+        let input = "1..99999999999999999999\n";
+
+        let err: ParseError = parse_plan(input).unwrap_err().into();
+        assert!(matches!(err, ParseError::PlanCountOverflow(count) if count == "99999999999999999999"));
+    }
+
+    #[test]
+    fn version_overflow() {
+        let err: ParseError = parse_version("TAP version 99999999999\n").unwrap_err().into();
+        assert!(matches!(&err, ParseError::VersionOverflow(version) if version == "99999999999"), "{err:?}");
+    }
+
+    #[test]
+    fn test_number_overflow() {
+        let err = parse_document("ok 99999999999999999999\n").unwrap_err();
+        assert!(matches!(&err, ParseError::TestNumberOverflow(number) if number == "99999999999999999999"), "{err:?}");
+
+        let err = "not ok 99999999999999999999".parse::<TestPoint>().unwrap_err();
+        assert!(matches!(err, ParseError::TestNumberOverflow(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_unspaced_number_overflow() {
+        let err = parse_document("ok99999999999999999999\n").unwrap_err();
+        assert!(matches!(&err, ParseError::TestNumberOverflow(number) if number == "99999999999999999999"), "{err:?}");
+    }
+
     #[test]
     fn test_status() {
         let input = "ok";
@@ -199,17 +630,59 @@ mod test {
 
         let (remaining, parsed) = parse_description(input).unwrap();
         assert_eq!(remaining, "\n");
-        assert_eq!(parsed, expected);
+        assert_eq!(parsed, Some(expected));
     }
 
     #[test]
     fn test_description_with_dash() {
-        let input = " - this is a description #";
+        let input = " - this is a description # TODO\n";
         let expected = "this is a description";
 
         let (remaining, parsed) = parse_description(input).unwrap();
-        assert_eq!(remaining, " #");
-        assert_eq!(parsed, expected);
+        assert_eq!(remaining, " # TODO\n");
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test]
+    fn test_blank_description() {
+        let (_remaining, point) = parse_test_point("ok - \n").unwrap();
+        assert_eq!(point.description, None);
+
+        let (_remaining, point) = parse_test_point("ok -  # TODO\n").unwrap();
+        assert_eq!(point.description, None);
+        assert_eq!(point.directive, Some(TestDirective::Todo(None)));
+    }
+
+    #[test]
+    fn test_description_keeps_unknown_trailer() {
+        let (_, point) = parse_test_point("ok - do thing # note: retried\n").unwrap();
+        assert_eq!(point.description.as_deref(), Some("do thing # note: retried"));
+        assert_eq!(point.directive, None);
+
+        let (_, point) = parse_test_point("ok 1 - do thing # note: retried # TODO flaky\n").unwrap();
+        assert_eq!(point.description.as_deref(), Some("do thing # note: retried"));
+        assert_eq!(point.directive, Some(TestDirective::Todo(Some("flaky".to_string()))));
+    }
+
+    #[test]
+    fn test_description_containing_hash() {
+        let input = "ok - grep for # in file\n";
+        let (_remaining, tests) = parse_test_points(input).unwrap();
+
+        let expected = vec![
+            TestPoint {
+                status: true,
+                description: Some("grep for # in file".to_string()),
+                directive: None,
+                yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+                test_number: None,
+            }
+        ];
+        assert_eq!(tests, expected);
     }
 
     #[test]
@@ -252,6 +725,18 @@ mod test {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn test_parse_unspaced_directive_without_reason() {
+        let (remaining, parsed) = parse_directive(" #SKIP\n").unwrap();
+        assert_eq!(remaining, "\n");
+        assert_eq!(parsed, TestDirective::Skip(None));
+
+        let (remaining, point) = parse_test_point("ok - desc #SKIP\n").unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(point.description.as_deref(), Some("desc"));
+        assert_eq!(point.directive, Some(TestDirective::Skip(None)));
+    }
+
     #[test]
     fn test_parse_legacy_directive_with_reason() {
         let input = " #SKIPPED: real reason\n";
@@ -262,6 +747,26 @@ mod test {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn test_parse_directive_with_colon() {
+        let input = " # todo: later\n";
+        let expected = TestDirective::Todo(Some("later".to_string()));
+
+        let (remaining, parsed) = parse_directive(input).unwrap();
+        assert_eq!(remaining, "\n");
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_parse_not_a_directive() {
+        assert!(parse_directive(" # skipping ahead\n").is_err());
+        assert!(parse_directive(" # SKIP-because-x\n").is_err());
+
+        let (_remaining, point) = parse_test_point("ok 1 - fast # skipping ahead\n").unwrap();
+        assert_eq!(point.description.as_deref(), Some("fast # skipping ahead"));
+        assert_eq!(point.directive, None);
+    }
+
     #[test]
     fn parse_single_ok() {
         let input = "ok\n";
@@ -273,6 +778,10 @@ mod test {
                 description: None,
                 directive: None,
                 yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
                 test_number: None,
             }
         ];
@@ -291,6 +800,10 @@ mod test {
                 description: Some("this is a stupid description".to_string()),
                 directive: None,
                 yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
                 test_number: None,
             }
         ];
@@ -309,10 +822,227 @@ mod test {
                 description: Some("this is a stupid description".to_string()),
                 directive: Some(TestDirective::Skip(Some("stupid Legacy skip".to_string()))),
                 yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
                 test_number: Some(3),
             }
         ];
         assert_eq!(tests.len(), 1);
         assert_eq!(tests, expected);
     }
+
+    #[test]
+    fn parse_document_without_trailing_newline() {
+        let input = "ok 1\nok 2";
+        let details = parse_document(input).unwrap();
+
+        let expected: Vec<_> = [1, 2].into_iter().map(|test_number| {
+            TestDetails::TestPoint(TestPoint {
+                status: true,
+                description: None,
+                directive: None,
+                yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+                test_number: Some(test_number),
+            })
+        }).collect();
+        assert_eq!(details, expected);
+    }
+
+    #[test]
+    fn parse_point_after_yaml() {
+        let input = "not ok 1 - first\n  ---\n  message: boom\n  ...\nok 2 - second\n";
+        let (remaining, tests) = parse_test_points(input).unwrap();
+
+        let expected = vec![
+            TestPoint {
+                status: false,
+                description: Some("first".to_string()),
+                directive: None,
+                yaml: Some("message: boom\n".to_string()),
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+                test_number: Some(1),
+            },
+            TestPoint {
+                status: true,
+                description: Some("second".to_string()),
+                directive: None,
+                yaml: None,
+                truncated_yaml: false,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+                test_number: Some(2),
+            },
+        ];
+        assert_eq!(remaining, "");
+        assert_eq!(tests, expected);
+    }
+
+    #[test]
+    fn parse_nested_yaml() {
+        let top_level = "  ---\n  message: boom\n  data:\n    got: 1\n  ...\n";
+        let nested = "      ---\n      message: boom\n      data:\n        got: 1\n      ...\n";
+
+        let (_remaining, expected) = parse_yaml(top_level, 0).unwrap();
+        let (remaining, parsed) = parse_yaml(nested, 1).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(parsed, expected);
+        assert_eq!(parsed, "message: boom\ndata:\n  got: 1\n");
+    }
+
+    #[test]
+    fn parse_yaml_in_tab_indented_subtest() {
+        use crate::Indent;
+
+        let input = "\tnot ok 1 - inner\n\t  ---\n\t  message: boom\n\t  data:\n\t    got: 1\n\t  ...\n\t1..1\nnot ok 1 - outer\n";
+        let options = ParserOptions { subtest_indent: Indent::Tab, ..Default::default() };
+        let details = parse_document_with(input, &options).unwrap();
+
+        let TestDetails::Subtest(subtest) = &details[0] else {
+            panic!("expected a subtest, got {details:?}");
+        };
+        let TestDetails::TestPoint(point) = &subtest.details[0] else {
+            panic!("expected the inner test point, got {subtest:?}");
+        };
+        assert_eq!(point.yaml.as_deref(), Some("message: boom\ndata:\n  got: 1\n"));
+    }
+
+    #[test]
+    fn parse_yaml_terminator_with_trailing_spaces() {
+        let input = "  ---\n  message: boom\n  ...   \nok 2\n";
+
+        let (remaining, parsed) = parse_yaml(input, 0).unwrap();
+        assert_eq!(remaining, "ok 2\n");
+        assert_eq!(parsed, "message: boom\n");
+    }
+
+    #[test]
+    fn parse_document_with_truncated_yaml() {
+        let input = "not ok 1 - crashed\n  ---\n  message: out of mem";
+        let details = parse_document(input).unwrap();
+
+        let expected = vec![
+            TestDetails::TestPoint(TestPoint {
+                status: false,
+                description: Some("crashed".to_string()),
+                directive: None,
+                yaml: Some("message: out of mem\n".to_string()),
+                truncated_yaml: true,
+                comments: Vec::new(),
+                #[cfg(feature = "serde")]
+                extra: None,
+                test_number: Some(1),
+            }),
+        ];
+        assert_eq!(details, expected);
+    }
+
+    #[test]
+    fn parse_test_point_ref_borrows_input() {
+        let input = "not ok 1 - borrowed\n  ---\n  got: 1\n  ...\n";
+        let (remaining, point) = parse_test_point_ref(input).unwrap();
+        assert_eq!(remaining, "");
+
+        let description = point.description.unwrap();
+        assert_eq!(description, "borrowed");
+        assert!(input.as_bytes().as_ptr_range().contains(&description.as_ptr()));
+        assert_eq!(point.yaml, Some("  got: 1\n"));
+
+        let (_remaining, owned) = parse_test_point(input).unwrap();
+        assert_eq!(point.to_owned(), owned);
+    }
+
+    #[test]
+    fn parse_test_point_refs_borrow_every_description() {
+        let input: String = (1..=1000).map(|n| format!("ok {n} - description number {n}\n")).collect();
+        let (remaining, points) = parse_test_point_refs(&input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(points.len(), 1000);
+
+        // none of the descriptions were copied, they all point into the input
+        let range = input.as_bytes().as_ptr_range();
+        assert!(points.iter().all(|point| range.contains(&point.description.unwrap().as_ptr())));
+        assert_eq!(points[999].description, Some("description number 1000"));
+    }
+
+    #[test]
+    fn parse_comment_detail() {
+        let (remaining, detail) = parse_detail("# hello\n", &ParserOptions::default()).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(detail, TestDetails::Comment(Comment("hello".into())));
+    }
+
+    #[test]
+    fn parse_yaml_after_comment_is_not_attached() {
+        let input = "ok 1\n# in between\n  ---\n  got: 1\n  ...\n";
+
+        let details = parse_document(input).unwrap();
+        let TestDetails::TestPoint(point) = &details[0] else {
+            panic!("expected a test point first, got {details:?}");
+        };
+        assert_eq!(point.yaml, None);
+        assert_eq!(details[1], TestDetails::Comment(Comment("in between".into())));
+    }
+
+    #[test]
+    fn parse_document_with_two_plans() {
+        let input = "1..2\nok 1\nok 2\n1..2\n";
+
+        let err = parse_document(input).unwrap_err();
+        assert!(matches!(err, ParseError::DuplicatePlan(plan) if plan == TestPlan::new(2)));
+    }
+
+    #[test]
+    fn parse_named_subtest() {
+        let input = "# Subtest: auth tests\n    ok 1 - login\n    1..1\nok 1 - auth tests\n";
+
+        let details = parse_document(input).unwrap();
+        let TestDetails::Subtest(subtest) = &details[0] else {
+            panic!("expected a subtest first, got {details:?}");
+        };
+        assert_eq!(subtest.name.as_deref(), Some("auth tests"));
+        assert_eq!(subtest.details.len(), 2);
+    }
+
+    #[test]
+    fn parse_subtest_indentation() {
+        use crate::Indent;
+
+        let nested = |indent: &str| {
+            format!("{indent}{indent}ok 1 - leaf\n{indent}{indent}1..1\n{indent}ok 1 - inner\n{indent}1..1\nok 1 - outer\n")
+        };
+
+        for indent in [Indent::Spaces(2), Indent::Spaces(4), Indent::Tab] {
+            let options = ParserOptions { subtest_indent: indent, ..Default::default() };
+            let details = parse_document_with(&nested(&indent.to_string()), &options).unwrap();
+
+            let [TestDetails::Subtest(outer), TestDetails::TestPoint(_)] = &details[..] else {
+                panic!("expected a subtest and its test point, got {details:?}");
+            };
+            let TestDetails::Subtest(inner) = &outer.details[0] else {
+                panic!("expected a nested subtest, got {outer:?}");
+            };
+            assert!(matches!(&inner.details[0], TestDetails::TestPoint(point) if point.description.as_deref() == Some("leaf")));
+        }
+    }
+
+    #[test]
+    fn parse_unspaced_test_number() {
+        let lenient = ParserOptions::default();
+        let (_remaining, detail) = parse_detail("ok1\n", &lenient).unwrap();
+        assert!(matches!(detail, TestDetails::TestPoint(point) if point.test_number == Some(1)));
+
+        let strict = ParserOptions { strict: true, ..Default::default() };
+        let (_remaining, detail) = parse_detail("not ok2\n", &strict).unwrap();
+        assert!(matches!(detail, TestDetails::Anything(_)));
+    }
 }
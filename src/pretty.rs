@@ -0,0 +1,76 @@
+//! A human-readable report for the terminal.
+
+use std::io::{self, Write};
+use crate::{TestOutcome, TestPoint, TestSuite};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Prints one line per test point followed by the totals. Without `color` no ANSI codes are
+/// emitted, so the output can be piped.
+pub fn print_summary(suite: &TestSuite, writer: &mut impl Write, color: bool) -> io::Result<()> {
+    for point in suite.test_points() {
+        print_point(point, writer, color)?;
+    }
+
+    let summary = suite.summary();
+    writeln!(
+        writer,
+        "{} tests, {} passed, {} failed, {} skipped, {} todo",
+        summary.total, summary.passed, summary.failed, summary.skipped, summary.todo,
+    )
+}
+
+fn print_point(point: &TestPoint, writer: &mut impl Write, color: bool) -> io::Result<()> {
+    let outcome = point.outcome();
+    let status = if point.status { "ok" } else { "not ok" };
+
+    if color {
+        let color = match outcome {
+            TestOutcome::Passed => GREEN,
+            TestOutcome::Failed => RED,
+            TestOutcome::Skipped | TestOutcome::TodoExpectedFail | TestOutcome::TodoUnexpectedPass => YELLOW,
+        };
+        write!(writer, "{color}{status}{RESET}")?;
+    } else {
+        write!(writer, "{status}")?;
+    }
+
+    if let Some(number) = point.test_number {
+        write!(writer, " {number}")?;
+    }
+    if let Some(description) = &point.description {
+        write!(writer, " - {description}")?;
+    }
+    if let Some(directive) = &point.directive {
+        write!(writer, " {directive}")?;
+    }
+    writeln!(writer)?;
+
+    if outcome == TestOutcome::Failed {
+        for line in point.yaml.iter().flat_map(|yaml| yaml.lines()) {
+            writeln!(writer, "    {line}")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn print_summary_without_color() {
+        let suite = TestSuite::parse("TAP Version 14\n1..3\nok 1 - fine\nnot ok 2 - broken\n  ---\n  got: 1\n  ...\nok 3 # SKIP later\n").unwrap();
+
+        let mut output = Vec::new();
+        print_summary(&suite, &mut output, false).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!output.contains('\x1b'));
+        assert_eq!(output, "ok 1 - fine\nnot ok 2 - broken\n    got: 1\nok 3 # SKIP later\n3 tests, 1 passed, 1 failed, 1 skipped, 0 todo\n");
+    }
+}
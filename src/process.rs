@@ -0,0 +1,53 @@
+use std::process::{Command, Stdio};
+use crate::{parse_bytes, ParseError, TestSuite};
+
+/// Runs a test binary to completion and parses its stdout as TAP.
+///
+/// A process that exits unsuccessfully without reporting a single failure most likely crashed
+/// before it could, so that is reported as [`ParseError::ProcessFailed`] instead of a passing run.
+pub fn run_command(cmd: &mut Command) -> Result<TestSuite, ParseError> {
+    let output = cmd.stdout(Stdio::piped()).output()?;
+    let suite = parse_bytes(&output.stdout)?;
+
+    if !output.status.success() && suite.failures().is_empty() {
+        return Err(ParseError::ProcessFailed(output.status));
+    }
+    Ok(suite)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[test]
+    fn run_passing_script() {
+        let suite = run_command(&mut sh("printf 'TAP Version 14\\n1..2\\nok 1\\nok 2 - second\\n'")).unwrap();
+
+        assert_eq!(suite.version, 14);
+        assert_eq!(suite.summary().passed, 2);
+    }
+
+    #[test]
+    fn run_failing_script() {
+        let suite = run_command(&mut sh("printf 'ok 1\\nnot ok 2\\n'; exit 1")).unwrap();
+        assert_eq!(suite.summary().failed, 1);
+    }
+
+    #[test]
+    fn run_crashing_script() {
+        let err = run_command(&mut sh("printf '1..2\\nok 1\\n'; exit 3")).unwrap_err();
+        assert!(matches!(err, ParseError::ProcessFailed(status) if status.code() == Some(3)));
+    }
+
+    #[test]
+    fn run_script_printing_invalid_utf8() {
+        let err = run_command(&mut sh("printf 'ok 1 - \\377\\n'")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidUtf8(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+use futures::{stream, Stream, StreamExt};
+use futures_timer::Delay;
+use crate::{TestDetails, TestSuite};
+
+/// Re-emits the details of a parsed suite as if they were arriving live, waiting `delay` before
+/// each one. Meant for testing and demoing consumers of the streaming API.
+pub fn replay(suite: TestSuite, delay: Duration) -> impl Stream<Item = TestDetails> {
+    stream::iter(suite.details).then(move |detail| async move {
+        if !delay.is_zero() {
+            Delay::new(delay).await;
+        }
+        detail
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use super::*;
+
+    #[test]
+    fn replay_in_order() {
+        let suite = TestSuite::parse("TAP Version 14\n1..2\nok 1\n# comment\nnot ok 2\n").unwrap();
+
+        let replayed: Vec<_> = block_on(replay(suite.clone(), Duration::ZERO).collect());
+        assert_eq!(replayed, suite.details);
+    }
+
+    #[test]
+    fn replay_with_delay() {
+        let suite = TestSuite::parse("ok 1\nok 2\n").unwrap();
+
+        let replayed: Vec<_> = block_on(replay(suite.clone(), Duration::from_millis(1)).collect());
+        assert_eq!(replayed, suite.details);
+    }
+}
@@ -0,0 +1,156 @@
+//! A JSON report with a stable schema, meant as an integration contract for external tools. The
+//! field names here are deliberately decoupled from the internal types and must not change when
+//! those do:
+//!
+//! ```json
+//! {
+//!   "version": 14,
+//!   "plan": { "count": 2, "start": 1, "end": 2, "directive": null, "reason": null, "label": null },
+//!   "summary": { "total": 2, "passed": 1, "failed": 1, "skipped": 0, "todo": 0 },
+//!   "tests": [
+//!     { "ok": true, "number": 1, "description": "works", "directive": null, "yaml": null },
+//!     { "ok": false, "number": 2, "description": null, "directive": { "kind": "todo", "reason": null }, "yaml": "got: 1\n" }
+//!   ]
+//! }
+//! ```
+//!
+//! `plan` is `null` for a run without a plan. Its `directive` is shaped like a test's, so that a
+//! `1..0 # SKIP` run can be told apart from an empty one, its `reason` holds the free text given
+//! as `1..0 # reason` instead. `yaml` holds the raw diagnostics block.
+
+use std::fmt::{self, Write};
+use crate::{TestDirective, TestPlan, TestPoint, TestSuite};
+
+pub fn to_json_report(suite: &TestSuite) -> String {
+    let mut json = String::new();
+    write_report(&mut json, suite).expect("writing to a String can't fail");
+    json
+}
+
+fn write_report(json: &mut String, suite: &TestSuite) -> fmt::Result {
+    write!(json, r#"{{"version":{},"plan":"#, suite.version)?;
+    match &suite.plan {
+        Some(plan) => write_plan(json, plan)?,
+        None => json.push_str("null"),
+    }
+
+    let summary = suite.summary();
+    write!(
+        json,
+        r#","summary":{{"total":{},"passed":{},"failed":{},"skipped":{},"todo":{}}}"#,
+        summary.total, summary.passed, summary.failed, summary.skipped, summary.todo,
+    )?;
+
+    json.push_str(r#","tests":["#);
+    for (index, point) in suite.test_points().into_iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        write_test(json, point)?;
+    }
+    json.push_str("]}");
+
+    Ok(())
+}
+
+fn write_test(json: &mut String, point: &TestPoint) -> fmt::Result {
+    write!(json, r#"{{"ok":{},"number":"#, point.status)?;
+    match point.test_number {
+        Some(number) => write!(json, "{number}")?,
+        None => json.push_str("null"),
+    }
+
+    json.push_str(r#","description":"#);
+    write_optional_string(json, point.description.as_deref());
+
+    json.push_str(r#","directive":"#);
+    write_directive(json, point.directive.as_ref())?;
+
+    json.push_str(r#","yaml":"#);
+    write_optional_string(json, point.yaml.as_deref());
+    json.push('}');
+
+    Ok(())
+}
+
+fn write_plan(json: &mut String, plan: &TestPlan) -> fmt::Result {
+    write!(json, r#"{{"count":{},"start":{},"end":{},"directive":"#, plan.count(), plan.start, plan.end)?;
+    write_directive(json, plan.directive.as_ref())?;
+    json.push_str(r#","reason":"#);
+    write_optional_string(json, plan.reason.as_deref());
+    json.push_str(r#","label":"#);
+    write_optional_string(json, plan.label.as_deref());
+    json.push('}');
+
+    Ok(())
+}
+
+fn write_directive(json: &mut String, directive: Option<&TestDirective>) -> fmt::Result {
+    let Some(directive) = directive else {
+        json.push_str("null");
+        return Ok(());
+    };
+
+    let (kind, reason) = match directive {
+        TestDirective::Todo(reason) => ("todo", reason),
+        TestDirective::Skip(reason) => ("skip", reason),
+    };
+    write!(json, r#"{{"kind":"{kind}","reason":"#)?;
+    write_optional_string(json, reason.as_deref());
+    json.push('}');
+
+    Ok(())
+}
+
+fn write_optional_string(json: &mut String, string: Option<&str>) {
+    let Some(string) = string else {
+        json.push_str("null");
+        return;
+    };
+
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str(r#"\""#),
+            '\\' => json.push_str(r"\\"),
+            '\n' => json.push_str(r"\n"),
+            '\r' => json.push_str(r"\r"),
+            '\t' => json.push_str(r"\t"),
+            c if c.is_control() => json.push_str(&format!(r"\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn small_run() {
+        let input = "TAP Version 14\n1..3\nok 1 - \"quoted\"\nnot ok 2\n  ---\n  got: 1\n  ...\nnot ok 3 # TODO\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        let expected = concat!(
+            r#"{"version":14,"plan":{"count":3,"start":1,"end":3,"directive":null,"reason":null,"label":null},"#,
+            r#""summary":{"total":3,"passed":1,"failed":1,"skipped":0,"todo":1},"tests":["#,
+            r#"{"ok":true,"number":1,"description":"\"quoted\"","directive":null,"yaml":null},"#,
+            r#"{"ok":false,"number":2,"description":null,"directive":null,"yaml":"got: 1\n"},"#,
+            r#"{"ok":false,"number":3,"description":null,"directive":{"kind":"todo","reason":null},"yaml":null}"#,
+            "]}",
+        );
+        assert_eq!(to_json_report(&suite), expected);
+    }
+
+    #[test]
+    fn skipped_plan() {
+        let skipped = TestSuite::parse("TAP Version 14\n1..0 # SKIP no network\n").unwrap();
+        let empty = TestSuite::parse("TAP Version 14\n1..0\n").unwrap();
+
+        let skipped = to_json_report(&skipped);
+        assert!(skipped.contains(r#""plan":{"count":0,"start":1,"end":0,"directive":{"kind":"skip","reason":"no network"},"reason":null,"label":null}"#), "{skipped}");
+        let empty = to_json_report(&empty);
+        assert!(empty.contains(r#""plan":{"count":0,"start":1,"end":0,"directive":null,"reason":null,"label":null}"#), "{empty}");
+    }
+}
@@ -0,0 +1,58 @@
+use futures::channel::mpsc::{channel, Receiver};
+use futures::{AsyncBufRead, SinkExt, StreamExt};
+use crate::{ParseError, Parser, TestDetails};
+
+/// How many parsed details may queue up before the parser waits for the consumer to catch up.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Parses `reader` on a task of the current tokio runtime, forwarding everything it parses through
+/// a bounded channel. The parser stops once the receiver is dropped.
+///
+/// # Panics
+///
+/// When called outside of a tokio runtime.
+pub fn spawn_parser<T>(reader: T) -> Receiver<Result<TestDetails, ParseError>>
+    where
+        T: AsyncBufRead + Unpin + Send + 'static,
+{
+    let (mut tx, rx) = channel(CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let parser = match Parser::new(reader).await {
+            Ok(parser) => parser,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return;
+            }
+        };
+
+        let mut details = parser.test_results();
+        while let Some(detail) = details.next().await {
+            // sending waits while the channel is full, which is what applies the backpressure
+            if tx.send(detail.map_err(ParseError::from)).await.is_err() {
+                // nobody is listening anymore
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod test {
+    use futures::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn receive_everything() {
+        let input = "TAP Version 14\n1..2\nok 1\nnot ok 2\n";
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        let details: Vec<_> = runtime.block_on(async { spawn_parser(Cursor::new(input.as_bytes())).collect().await });
+        let details: Vec<_> = details.into_iter().map(Result::unwrap).collect();
+        assert_eq!(details.len(), 3);
+        assert!(matches!(details[0], TestDetails::TestPlan(_)));
+        assert!(matches!(&details[2], TestDetails::TestPoint(point) if !point.status));
+    }
+}
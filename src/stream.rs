@@ -0,0 +1,522 @@
+use std::io;
+use std::pin::{pin, Pin};
+use std::task::{Context, Poll};
+use futures::channel::mpsc::Sender;
+use futures::io::BufReader;
+use futures::{future, ready, AsyncBufRead, AsyncRead, SinkExt, Stream, StreamExt};
+use pin_project::pin_project;
+use crate::{ParseError, Parser, ResultStream, Summary, TestDetails, TestOutcome, TestPoint, TestSuite};
+
+impl<T> ResultStream<T>
+    where
+        T: AsyncBufRead
+{
+    /// Allows looking at the next detail before pulling it.
+    pub fn peekable(self) -> PeekableResultStream<T> {
+        PeekableResultStream {
+            stream: self,
+            peeked: None,
+        }
+    }
+
+    /// Pairs each parsed detail with the text it was parsed from, including the yaml block of a
+    /// test point. The text is only kept if [`keep_raw`](crate::ParserOptions::keep_raw) is enabled, it is empty
+    /// otherwise.
+    pub fn raw_details(self) -> RawDetails<T> {
+        RawDetails {
+            stream: self,
+        }
+    }
+
+    /// Pairs each parsed detail with how deeply it was indented, in levels of subtest indentation.
+    /// Details are only accepted indented if
+    /// [`allow_indented`](crate::ParserOptions::allow_indented) is enabled, otherwise the depth is
+    /// always 0.
+    pub fn with_depth(self) -> IndentedDetails<T> {
+        IndentedDetails {
+            stream: self,
+        }
+    }
+
+    /// Yields plain details until the first error, which ends the stream and is kept for
+    /// [`FallibleResultStream::take_error`].
+    pub fn into_fallible(self) -> FallibleResultStream<T> {
+        FallibleResultStream {
+            stream: self,
+            error: None,
+            failed: false,
+        }
+    }
+
+    /// Pairs each parsed detail with the summary of the run up to and including it, e.g. to keep
+    /// a progress display up to date without collecting the whole run.
+    pub fn summarize(self) -> impl Stream<Item = io::Result<(TestDetails, Summary)>> {
+        self.scan(Summary::default(), |summary, detail| {
+            let item = detail.map(|detail| {
+                summary.add(&detail);
+                (detail, *summary)
+            });
+            future::ready(Some(item))
+        })
+    }
+
+    /// Drains the stream into a suite. Should it fail partway, the error carries everything parsed
+    /// up to then.
+    pub async fn collect_suite(self) -> Result<TestSuite, ParseError> {
+        let version = self.version;
+        let mut details = Vec::new();
+
+        let mut stream = pin!(self);
+        while let Some(detail) = stream.next().await {
+            match detail {
+                Ok(detail) => details.push(detail),
+                Err(error) => {
+                    let suite = Box::new(TestSuite::from_details(version, details));
+                    return Err(ParseError::Interrupted { suite, error });
+                }
+            }
+        }
+
+        Ok(TestSuite::from_details(version, details))
+    }
+
+    /// Collects the test points of the stream, stopping at the first error.
+    pub async fn try_collect_points(self) -> Result<Vec<TestPoint>, ParseError> {
+        let mut points = Vec::new();
+
+        let mut stream = pin!(self.enumerate());
+        while let Some((index, detail)) = stream.next().await {
+            match detail {
+                Ok(TestDetails::TestPoint(point)) => points.push(point),
+                Ok(_) => {}
+                Err(error) => {
+                    let line = match error.get_ref().and_then(|error| error.downcast_ref()) {
+                        Some(ParseError::Unparsed { line, .. }) => Some(line.clone()),
+                        _ => None,
+                    };
+                    return Err(ParseError::CollectFailed { points: points.len(), index, line, error });
+                }
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// Counts the test points of the stream without keeping any of them around.
+    pub async fn count(self) -> Result<usize, ParseError> {
+        let mut count = 0;
+
+        let mut stream = pin!(self);
+        while let Some(detail) = stream.next().await {
+            if let TestDetails::TestPoint(_) = detail? {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Sends every detail to `tx`, waiting for room in the channel before parsing on. Stops at the
+    /// first error, a receiver that went away is reported as a broken pipe.
+    pub async fn forward_to(self, mut tx: Sender<TestDetails>) -> Result<(), ParseError> {
+        let mut stream = pin!(self);
+        while let Some(detail) = stream.next().await {
+            tx.send(detail?)
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Only the test points that failed without a directive excusing them, errors still come
+    /// through.
+    pub fn failures(self) -> impl Stream<Item = io::Result<TestPoint>> {
+        self.filter_map(|detail| future::ready(match detail {
+            Ok(TestDetails::TestPoint(point)) if point.outcome() == TestOutcome::Failed => Some(Ok(point)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }))
+    }
+
+    /// Converts each test point as it arrives. Every other detail is dropped, errors still come
+    /// through.
+    pub fn map_points<F, U>(self, mut f: F) -> impl Stream<Item = io::Result<U>>
+        where
+            F: FnMut(TestPoint) -> U,
+    {
+        self.filter_map(move |detail| future::ready(match detail {
+            Ok(TestDetails::TestPoint(point)) => Some(Ok(f(point))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }))
+    }
+
+    /// Pairs each test point with its position among the test points, counting from 0. Unlike
+    /// test numbers, the position is always there and always sequential.
+    pub fn enumerate_points(self) -> impl Stream<Item = io::Result<(usize, TestPoint)>> {
+        let mut index = 0;
+        self.map_points(move |point| {
+            index += 1;
+            (index - 1, point)
+        })
+    }
+}
+
+/// Parses everything `reader` produces into a suite, buffering it as needed.
+pub async fn parse_all(reader: impl AsyncRead + Unpin) -> Result<TestSuite, ParseError> {
+    let parser = Parser::new(BufReader::new(reader)).await?;
+    parser.test_results().collect_suite().await
+}
+
+/// A [`ResultStream`] that can look one detail ahead.
+#[pin_project]
+pub struct PeekableResultStream<T> {
+    #[pin]
+    stream: ResultStream<T>,
+    /// The item pulled ahead of time, `Some(None)` if the stream has ended.
+    peeked: Option<Option<io::Result<TestDetails>>>,
+}
+
+impl<T> PeekableResultStream<T>
+    where
+        T: AsyncBufRead + Unpin
+{
+    /// The next detail, without consuming it. Only a detail that has been completely parsed is
+    /// kept, so dropping the future halfway loses nothing.
+    pub async fn peek(&mut self) -> Option<&io::Result<TestDetails>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.stream.next().await);
+        }
+
+        self.peeked.as_ref().and_then(Option::as_ref)
+    }
+}
+
+impl<T> Stream for PeekableResultStream<T>
+    where
+        T: AsyncBufRead
+{
+    type Item = io::Result<TestDetails>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.peeked.take() {
+            Some(item) => Poll::Ready(item),
+            None => this.stream.poll_next(cx),
+        }
+    }
+}
+
+/// A [`ResultStream`] that yields the text of each detail alongside it.
+#[pin_project]
+pub struct RawDetails<T> {
+    #[pin]
+    stream: ResultStream<T>,
+}
+
+impl<T> Stream for RawDetails<T>
+    where
+        T: AsyncBufRead
+{
+    type Item = io::Result<(TestDetails, String)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut stream = self.project().stream;
+        let item = ready!(stream.as_mut().poll_next(cx));
+        let raw = stream.project().decoder.take_raw();
+
+        Poll::Ready(item.map(|detail| detail.map(|detail| (detail, raw))))
+    }
+}
+
+/// A [`ResultStream`] that ends at the first error instead of yielding it.
+#[pin_project]
+pub struct FallibleResultStream<T> {
+    #[pin]
+    stream: ResultStream<T>,
+    error: Option<io::Error>,
+    /// Whether the stream has ended with an error, it stays ended once the error is taken.
+    failed: bool,
+}
+
+impl<T> FallibleResultStream<T> {
+    /// The error that ended the stream, if any.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+impl<T> Stream for FallibleResultStream<T>
+    where
+        T: AsyncBufRead
+{
+    type Item = TestDetails;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.failed {
+            return Poll::Ready(None);
+        }
+
+        match ready!(this.stream.poll_next(cx)) {
+            Some(Ok(detail)) => Poll::Ready(Some(detail)),
+            Some(Err(error)) => {
+                *this.error = Some(error);
+                *this.failed = true;
+                Poll::Ready(None)
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// A [`ResultStream`] that yields the depth of each detail alongside it.
+#[pin_project]
+pub struct IndentedDetails<T> {
+    #[pin]
+    stream: ResultStream<T>,
+}
+
+impl<T> Stream for IndentedDetails<T>
+    where
+        T: AsyncBufRead
+{
+    type Item = io::Result<(TestDetails, usize)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut stream = self.project().stream;
+        let item = ready!(stream.as_mut().poll_next(cx));
+        let depth = stream.project().decoder.depth();
+
+        Poll::Ready(item.map(|detail| detail.map(|detail| (detail, depth))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::TryStreamExt;
+    use futures::StreamExt;
+    use crate::{ParseError, Parser, ParserBuilder, Summary, TestDetails, TestPlan};
+
+    #[test]
+    fn summarize() {
+        let input = "TAP Version 14\n1..3\nok 1\nnot ok 2\nok 3\n";
+
+        let summaries: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().summarize().map_ok(|(_detail, summary)| summary).try_collect().await.unwrap()
+        });
+
+        let passed: Vec<_> = summaries.iter().map(|summary| summary.passed).collect();
+        let failed: Vec<_> = summaries.iter().map(|summary| summary.failed).collect();
+        assert_eq!(passed, vec![0, 1, 1, 2]);
+        assert_eq!(failed, vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn collect_suite() {
+        let input = "TAP Version 14\n1..3\nok 1\nnot ok 2\nok 3 # SKIP\n";
+
+        let suite = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().collect_suite().await.unwrap()
+        });
+
+        assert_eq!(suite.version, 14);
+        assert_eq!(suite.plan.as_ref().map(|plan| plan.count()), Some(3));
+        assert_eq!(suite.summary(), Summary { total: 3, passed: 1, failed: 1, skipped: 1, todo: 0 });
+    }
+
+    #[test]
+    fn collect_suite_keeps_partial_suite() {
+        let chunks: Vec<io::Result<&[u8]>> = vec![
+            Ok(b"TAP Version 14\n"),
+            Ok(b"ok 1\nok 2\n"),
+            Err(io::Error::other("connection lost")),
+        ];
+        let reader = futures::stream::iter(chunks).into_async_read();
+
+        let err = block_on(async {
+            let parser = Parser::new(reader).await.unwrap();
+            parser.test_results().collect_suite().await.unwrap_err()
+        });
+
+        let ParseError::Interrupted { suite, .. } = err else {
+            panic!("expected the partial suite, got {err:?}");
+        };
+        assert_eq!(suite.test_points().len(), 2);
+    }
+
+    #[test]
+    fn failures() {
+        let input = "TAP Version 14\nok 1\nnot ok 2\nnot ok 3 # TODO\nnot ok 4 # SKIP\nnot ok 5\n";
+
+        let failures: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().failures().try_collect().await.unwrap()
+        });
+
+        let numbers: Vec<_> = failures.iter().map(|point| point.test_number).collect();
+        assert_eq!(numbers, vec![Some(2), Some(5)]);
+    }
+
+    #[test]
+    fn map_points() {
+        let input = "TAP Version 14\n1..2\nok 1 - first\n# between\nok 2 - second\n";
+
+        let descriptions: Vec<_> = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().map_points(|point| point.description).try_collect().await.unwrap()
+        });
+
+        assert_eq!(descriptions, vec![Some("first".to_string()), Some("second".to_string())]);
+    }
+
+    #[test]
+    fn collect_empty_suite() {
+        let suite = block_on(async {
+            let parser = Parser::new(Cursor::new(&b""[..])).await.unwrap();
+            parser.test_results().collect_suite().await.unwrap()
+        });
+
+        assert_eq!(suite.version, 12);
+        assert_eq!(suite.test_points().len(), 0);
+    }
+
+    #[test]
+    fn try_collect_points_stops_at_error() {
+        let input = "TAP Version 14\nok 1\nok 2\nthis is not tap\nok 3\n";
+
+        let err = block_on(async {
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().try_collect_points().await.unwrap_err()
+        });
+
+        let ParseError::CollectFailed { points, index, line, .. } = err else {
+            panic!("expected the collection to fail, got {err:?}");
+        };
+        assert_eq!((points, index), (2, 2));
+        assert_eq!(line.as_deref(), Some("this is not tap"));
+    }
+
+    #[test]
+    fn count() {
+        let input = "TAP Version 14\n1..3\nok 1\n# a comment\nnot ok 2\n# another one\nok 3\n";
+
+        let count = block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            parser.test_results().count().await.unwrap()
+        });
+
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn peek_at_plan() {
+        let input = "TAP Version 14\n1..2\nok 1\nok 2\n";
+
+        block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            let mut results = parser.test_results().peekable();
+
+            let peeked = results.peek().await;
+            assert!(matches!(peeked, Some(Ok(TestDetails::TestPlan(plan))) if plan.count() == 2));
+            // peeking again doesn't advance
+            assert!(matches!(results.peek().await, Some(Ok(TestDetails::TestPlan(_)))));
+
+            let pulled = results.next().await.unwrap().unwrap();
+            assert_eq!(pulled, TestDetails::TestPlan(TestPlan::new(2)));
+            assert!(matches!(results.next().await, Some(Ok(TestDetails::TestPoint(_)))));
+        });
+    }
+
+    #[test]
+    fn raw_details_round_trip() {
+        let body = "1..2\nok 1 - first\nnot ok 2 - second\n  ---\n  got: 1\n  ...\n# done\n";
+        let input = format!("TAP Version 14\n{body}");
+        block_on(async {
+            let parser = ParserBuilder::new().keep_raw(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            let details: Vec<(TestDetails, String)> = parser.test_results().raw_details().try_collect().await.unwrap();
+
+            assert_eq!(details.len(), 4);
+            assert_eq!(details[2].1, "not ok 2 - second\n  ---\n  got: 1\n  ...\n");
+            assert_eq!(details.iter().map(|(_, raw)| raw.as_str()).collect::<String>(), body);
+        });
+    }
+
+    #[test]
+    fn forward_to_channel() {
+        let input = "TAP Version 14\n1..2\nok 1\n# between\nok 2\n";
+        block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            // a single slot makes the stream wait for the receiver
+            let (tx, rx) = futures::channel::mpsc::channel(1);
+
+            let (forwarded, received) = futures::join!(parser.test_results().forward_to(tx), rx.collect::<Vec<_>>());
+            forwarded.unwrap();
+            assert_eq!(received.len(), 4);
+            assert_eq!(received[0], TestDetails::TestPlan(TestPlan::new(2)));
+            assert!(matches!(&received[3], TestDetails::TestPoint(point) if point.test_number == Some(2)));
+        });
+    }
+
+    #[test]
+    fn indented_details() {
+        let input = "TAP Version 14\n    ok 1 - child\n        # nested\n    1..1\nok 1 - parent\n";
+        block_on(async {
+            let parser = ParserBuilder::new().allow_indented(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            let details: Vec<(TestDetails, usize)> = parser.test_results().with_depth().try_collect().await.unwrap();
+
+            let [(TestDetails::TestPoint(child), 1), (TestDetails::Comment(comment), 2), (TestDetails::TestPlan(_), 1), (TestDetails::TestPoint(parent), 0)] = &details[..] else {
+                panic!("expected the child point at depth 1, got {details:?}");
+            };
+            assert_eq!(child.description.as_deref(), Some("child"));
+            assert_eq!(comment.0, "nested");
+            assert_eq!(parent.description.as_deref(), Some("parent"));
+        });
+    }
+
+    #[test]
+    fn fallible_stream_keeps_error() {
+        let input = "TAP Version 14\nok 1\nwhat\nok 2\n";
+        block_on(async {
+            let parser = ParserBuilder::new().strict(true).build(Cursor::new(input.as_bytes())).await.unwrap();
+            let mut results = parser.test_results().into_fallible();
+
+            assert!(matches!(results.next().await, Some(TestDetails::TestPoint(_))));
+            assert_eq!(results.next().await, None);
+            assert_eq!(results.next().await, None);
+
+            let error: ParseError = results.take_error().unwrap().into();
+            assert!(matches!(error, ParseError::Unparsed { ref line, .. } if line == "what"), "{error:?}");
+            assert!(results.take_error().is_none());
+        });
+    }
+
+    #[test]
+    fn parse_all_in_one_call() {
+        let input = "TAP Version 14\n1..3\nok 1 - first\nnot ok 2 - second\n  ---\n  got: 1\n  ...\nok 3 # SKIP\n";
+        let suite = block_on(super::parse_all(input.as_bytes())).unwrap();
+
+        assert_eq!(suite.version, 14);
+        assert_eq!(suite.plan, Some(TestPlan::new(3)));
+        assert_eq!(suite.summary(), Summary { total: 3, passed: 1, failed: 1, skipped: 1, todo: 0 });
+    }
+
+    #[test]
+    fn enumerate_points() {
+        let input = "TAP Version 14\n1..3\nok 7\n# between\nok\nnot ok 2\n";
+        block_on(async {
+            let parser = Parser::new(Cursor::new(input.as_bytes())).await.unwrap();
+            let points: Vec<_> = parser.test_results().enumerate_points().try_collect().await.unwrap();
+
+            let positions: Vec<_> = points.iter().map(|(index, point)| (*index, point.test_number)).collect();
+            assert_eq!(positions, vec![(0, Some(7)), (1, None), (2, Some(2))]);
+        });
+    }
+}
@@ -0,0 +1,79 @@
+use futures::{future, stream, Stream, StreamExt};
+use crate::TestDetails;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum SubtestGroup {
+    /// Consecutive top level details that sit between subtests.
+    TopLevel(Vec<TestDetails>),
+    /// The children of a single subtest.
+    Subtest(Vec<TestDetails>),
+}
+
+/// Splits a stream of details into chunks at each subtest boundary, so each subtest (and each run
+/// of top level details in between) can be processed on its own.
+pub fn split_subtests<S>(details: S) -> impl Stream<Item = SubtestGroup>
+    where
+        S: Stream<Item = TestDetails>
+{
+    // `None` marks the end of the stream so the last run of top level details can be flushed
+    details
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .scan(Vec::new(), |top_level: &mut Vec<TestDetails>, detail| {
+            let mut groups = Vec::new();
+            match detail {
+                Some(TestDetails::Subtest(subtest)) => {
+                    if !top_level.is_empty() {
+                        groups.push(SubtestGroup::TopLevel(std::mem::take(top_level)));
+                    }
+                    groups.push(SubtestGroup::Subtest(subtest.details));
+                }
+                Some(detail) => top_level.push(detail),
+                None if !top_level.is_empty() => {
+                    groups.push(SubtestGroup::TopLevel(std::mem::take(top_level)));
+                }
+                None => {}
+            }
+
+            future::ready(Some(stream::iter(groups)))
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use super::*;
+    use crate::parsing::parse_details;
+    use crate::{ParserOptions, TestPlan, TestPoint};
+
+    fn point(test_number: usize, description: &str) -> TestDetails {
+        TestDetails::TestPoint(TestPoint {
+            status: true,
+            test_number: Some(test_number),
+            description: Some(description.to_string()),
+            directive: None,
+            yaml: None,
+            truncated_yaml: false,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        })
+    }
+
+    #[test]
+    fn split_two_subtests() {
+        let input = "    ok 1 - child a\n    1..1\nok 1 - first\n    ok 1 - child b\n    1..1\nok 2 - second\n1..2\n";
+        let (_remaining, details) = parse_details(input, &ParserOptions::default()).unwrap();
+
+        let groups: Vec<_> = block_on(split_subtests(stream::iter(details)).collect());
+
+        let expected = vec![
+            SubtestGroup::Subtest(vec![point(1, "child a"), TestDetails::TestPlan(TestPlan::new(1))]),
+            SubtestGroup::TopLevel(vec![point(1, "first")]),
+            SubtestGroup::Subtest(vec![point(1, "child b"), TestDetails::TestPlan(TestPlan::new(1))]),
+            SubtestGroup::TopLevel(vec![point(2, "second"), TestDetails::TestPlan(TestPlan::new(2))]),
+        ];
+        assert_eq!(groups, expected);
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use crate::parsing::{parse_document_with, parse_version};
+use crate::{BailOut, ParseError, ParserOptions, TestDetails, TestDirective, TestOutcome, TestPlan, TestPoint};
+
+/// A fully parsed TAP run, for when the whole input is available up front.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct TestSuite {
+    pub version: u32,
+    pub plan: Option<TestPlan>,
+    pub details: Vec<TestDetails>,
+    pub bailed_out: Option<BailOut>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub todo: usize,
+}
+
+/// The test points of a run grouped by their outcome, each group in document order.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Partitioned<'a> {
+    pub passed: Vec<&'a TestPoint>,
+    pub failed: Vec<&'a TestPoint>,
+    pub skipped: Vec<&'a TestPoint>,
+    /// Todo tests, whether they failed as expected or passed unexpectedly.
+    pub todo: Vec<&'a TestPoint>,
+}
+
+impl Summary {
+    /// Tallies the top level test points, subtests are accounted for by the test point closing them.
+    pub fn from_details(details: &[TestDetails]) -> Summary {
+        let mut summary = Summary::default();
+        for detail in details {
+            summary.add(detail);
+        }
+        summary
+    }
+
+    /// Accounts for one more detail, anything that isn't a test point is ignored.
+    pub(crate) fn add(&mut self, detail: &TestDetails) {
+        if let TestDetails::TestPoint(point) = detail {
+            self.add_point(point);
+        }
+    }
+
+    fn add_point(&mut self, point: &TestPoint) {
+        self.total += 1;
+        match point.outcome() {
+            TestOutcome::Passed => self.passed += 1,
+            TestOutcome::Failed => self.failed += 1,
+            TestOutcome::Skipped => self.skipped += 1,
+            TestOutcome::TodoExpectedFail | TestOutcome::TodoUnexpectedPass => self.todo += 1,
+        }
+    }
+}
+
+impl TestSuite {
+    pub fn parse(input: &str) -> Result<TestSuite, ParseError> {
+        Self::parse_with(input, &ParserOptions::default())
+    }
+
+    pub fn parse_with(input: &str, options: &ParserOptions) -> Result<TestSuite, ParseError> {
+        // a document without a version line predates TAP 13
+        let (input, version) = match parse_version(input) {
+            Ok(parsed) => parsed,
+            Err(e @ nom::Err::Failure(_)) => return Err(e.into()),
+            Err(_) => (input, 12),
+        };
+        let details = parse_document_with(input, options)?;
+
+        Ok(TestSuite::from_details(version, details))
+    }
+
+    pub fn from_details(version: u32, details: Vec<TestDetails>) -> TestSuite {
+        let plan = details.iter().find_map(|detail| match detail {
+            TestDetails::TestPlan(plan) => Some(plan.clone()),
+            _ => None,
+        });
+        let bailed_out = details.iter().find_map(|detail| match detail {
+            TestDetails::BailOut(bail_out) => Some(bail_out.clone()),
+            _ => None,
+        });
+
+        TestSuite {
+            version,
+            plan,
+            details,
+            bailed_out,
+        }
+    }
+
+    pub fn test_points(&self) -> Vec<&TestPoint> {
+        self.details.iter().filter_map(|detail| match detail {
+            TestDetails::TestPoint(point) => Some(point),
+            _ => None,
+        }).collect()
+    }
+
+    /// Like [`TestSuite::test_points`], without collecting them.
+    pub fn points(&self) -> impl Iterator<Item = &TestPoint> {
+        self.details.iter().filter_map(|detail| match detail {
+            TestDetails::TestPoint(point) => Some(point),
+            _ => None,
+        })
+    }
+
+    /// Test points that failed without a directive excusing them.
+    pub fn failures(&self) -> Vec<&TestPoint> {
+        self.test_points()
+            .into_iter()
+            .filter(|point| point.outcome() == TestOutcome::Failed)
+            .collect()
+    }
+
+    pub fn passed(&self) -> Vec<&TestPoint> {
+        self.test_points()
+            .into_iter()
+            .filter(|point| point.outcome() == TestOutcome::Passed)
+            .collect()
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::from_details(&self.details)
+    }
+
+    /// Groups the test points by their outcome.
+    pub fn partition(&self) -> Partitioned<'_> {
+        let mut partitioned = Partitioned::default();
+        for point in self.points() {
+            let bucket = match point.outcome() {
+                TestOutcome::Passed => &mut partitioned.passed,
+                TestOutcome::Failed => &mut partitioned.failed,
+                TestOutcome::Skipped => &mut partitioned.skipped,
+                TestOutcome::TodoExpectedFail | TestOutcome::TodoUnexpectedPass => &mut partitioned.todo,
+            };
+            bucket.push(point);
+        }
+        partitioned
+    }
+
+    /// Keeps only the details `f` accepts, in their order. Test points keep their numbers, the plan
+    /// and bail out are updated should they be removed.
+    pub fn retain<F>(&mut self, f: F)
+        where
+            F: FnMut(&TestDetails) -> bool,
+    {
+        self.details.retain(f);
+
+        if !self.details.iter().any(|detail| matches!(detail, TestDetails::TestPlan(_))) {
+            self.plan = None;
+        }
+        if !self.details.iter().any(|detail| matches!(detail, TestDetails::BailOut(_))) {
+            self.bailed_out = None;
+        }
+    }
+
+    /// The run as canonical TAP, as written by [`Writer::write_canonical`](crate::Writer::write_canonical).
+    pub fn to_canonical_tap(&self) -> String {
+        let mut writer = crate::Writer::new(Vec::new());
+        writer.write_canonical(self).expect("writing to a Vec can't fail");
+        String::from_utf8(writer.into_inner()).expect("TAP is written as UTF-8")
+    }
+
+    /// Removes comments and empty lines.
+    pub fn strip_comments(&mut self) {
+        self.retain(|detail| !matches!(detail, TestDetails::Comment(_) | TestDetails::Empty));
+    }
+
+    /// Fills in the number of every test point that doesn't have one with its position in the run.
+    /// Explicitly numbered points keep their number and the count carries on from there. Should a
+    /// point be numbered `usize::MAX`, there's no number left for the points after it, which stay
+    /// unnumbered until the next explicitly numbered one.
+    pub fn number_points(&mut self) {
+        let mut next = Some(1);
+        for detail in &mut self.details {
+            let TestDetails::TestPoint(point) = detail else {
+                continue;
+            };
+
+            if point.test_number.is_none() {
+                point.test_number = next;
+            }
+            next = point.test_number.and_then(|number| number.checked_add(1));
+        }
+    }
+
+    /// Renders each test point as a single character in run order: `.` for a pass, `F` for a
+    /// failure, `s` for a skip and `t` for a todo.
+    pub fn sparkline(&self) -> String {
+        self.test_points()
+            .into_iter()
+            .map(|point| match point.outcome() {
+                TestOutcome::Passed => '.',
+                TestOutcome::Failed => 'F',
+                TestOutcome::Skipped => 's',
+                TestOutcome::TodoExpectedFail | TestOutcome::TodoUnexpectedPass => 't',
+            })
+            .collect()
+    }
+
+    /// The test numbers the plan promised that no test point reported, as ranges in ascending
+    /// order. Only explicitly numbered points are taken into account.
+    ///
+    /// Consecutive missing numbers are merged into one range, so a huge plan like `1..4294967295`
+    /// with no points gives a single range rather than billions of numbers. There are at most one
+    /// more ranges than there are test points.
+    pub fn missing_numbers(&self) -> Vec<RangeInclusive<usize>> {
+        let Some(plan) = &self.plan else {
+            return Vec::new();
+        };
+
+        let seen: BTreeSet<_> = self.test_points()
+            .into_iter()
+            .filter_map(|point| point.test_number)
+            .filter(|number| plan.start <= *number && *number <= plan.end)
+            .collect();
+
+        let mut missing = Vec::new();
+        let mut next = Some(plan.start);
+        for number in seen {
+            if let Some(first) = next.filter(|first| *first < number) {
+                missing.push(first..=number - 1);
+            }
+            next = number.checked_add(1);
+        }
+        if let Some(first) = next.filter(|first| *first <= plan.end) {
+            missing.push(first..=plan.end);
+        }
+        missing
+    }
+
+    /// The share of test time spent in points that didn't fail, weighted by the
+    /// [`TestPoint::duration`] of each point so slow failures count for more. Points without a
+    /// duration are left out, returns `None` if none of them have one.
+    pub fn time_weighted_pass_rate(&self) -> Option<f64> {
+        let mut total = 0.0;
+        let mut passed = 0.0;
+        for point in self.test_points() {
+            let Some(duration) = point.duration().map(|duration| duration.as_secs_f64()) else {
+                continue;
+            };
+
+            total += duration;
+            if point.is_successful() {
+                passed += duration;
+            }
+        }
+
+        (total > 0.0).then(|| passed / total)
+    }
+
+    /// Whether both runs have the same test points with the same outcomes, regardless of their
+    /// diagnostics, comments or anything else in between.
+    pub fn same_outcomes(&self, other: &TestSuite) -> bool {
+        self.points().count() == other.points().count()
+            && self.points().zip(other.points()).all(|(point, other)| point.eq_ignoring_diagnostics(other))
+    }
+
+    /// A digest of the outcome of the run, to tell whether the results changed between runs. Only
+    /// the status, number, description and directive of each test point go into it, so differing
+    /// diagnostics or comments don't matter.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        for point in self.points() {
+            hasher.write_u8(u8::from(point.status));
+            hasher.write_number(point.test_number);
+            hasher.write_text(point.description.as_deref());
+            let (tag, reason) = match &point.directive {
+                None => (0, None),
+                Some(TestDirective::Todo(reason)) => (1, reason.as_deref()),
+                Some(TestDirective::Skip(reason)) => (2, reason.as_deref()),
+            };
+            hasher.write_u8(tag);
+            hasher.write_text(reason);
+        }
+        hasher.finish()
+    }
+
+    /// The exit status a harness like `prove` reports for the run: 0 unless a test point failed
+    /// without a directive excusing it or the run bailed out. Unexpected TODO passes only warrant
+    /// a warning.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code_with(false)
+    }
+
+    /// Like [`TestSuite::exit_code`], optionally failing the run on TODO points that passed.
+    pub fn exit_code_with(&self, fail_unexpected_passes: bool) -> i32 {
+        let failed = self.test_points().into_iter().any(|point| match point.outcome() {
+            TestOutcome::Failed => true,
+            TestOutcome::TodoUnexpectedPass => fail_unexpected_passes,
+            _ => false,
+        });
+
+        if failed || self.bailed_out.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash. Unlike the standard library's hasher its output is specified, so
+/// fingerprints can be compared across builds. That only holds for the bytes written explicitly,
+/// which is why values aren't fed to it through `Hash`, whose output differs between platforms.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    /// Writes a number as 8 little endian bytes, whatever the width of `usize`, after a byte
+    /// telling whether there is one.
+    fn write_number(&mut self, number: Option<usize>) {
+        self.write_u8(u8::from(number.is_some()));
+        if let Some(number) = number {
+            self.write(&(number as u64).to_le_bytes());
+        }
+    }
+
+    /// Writes a text prefixed with its length, so that consecutive texts can't run into each
+    /// other, after a byte telling whether there is one.
+    fn write_text(&mut self, text: Option<&str>) {
+        self.write_u8(u8::from(text.is_some()));
+        if let Some(text) = text {
+            self.write(&(text.len() as u64).to_le_bytes());
+            self.write(text.as_bytes());
+        }
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Accumulates details into a [`TestSuite`], for producing TAP rather than parsing it.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct SuiteBuilder {
+    version: u32,
+    details: Vec<TestDetails>,
+}
+
+impl Default for SuiteBuilder {
+    fn default() -> SuiteBuilder {
+        SuiteBuilder {
+            version: 14,
+            details: Vec::new(),
+        }
+    }
+}
+
+impl SuiteBuilder {
+    pub fn new() -> SuiteBuilder {
+        SuiteBuilder::default()
+    }
+
+    pub fn push(&mut self, detail: TestDetails) {
+        self.details.push(detail);
+    }
+
+    /// Builds the suite, planning for the test points it holds unless a plan was pushed.
+    pub fn finish(mut self) -> TestSuite {
+        let planned = self.details.iter().any(|detail| matches!(detail, TestDetails::TestPlan(_)));
+        if !planned {
+            let count = self.details.iter().filter(|detail| matches!(detail, TestDetails::TestPoint(_))).count();
+            self.details.insert(0, TestDetails::TestPlan(TestPlan::new(count)));
+        }
+
+        TestSuite::from_details(self.version, self.details)
+    }
+}
+
+impl Extend<TestDetails> for SuiteBuilder {
+    fn extend<I: IntoIterator<Item = TestDetails>>(&mut self, details: I) {
+        self.details.extend(details);
+    }
+}
+
+impl FromIterator<TestDetails> for SuiteBuilder {
+    fn from_iter<I: IntoIterator<Item = TestDetails>>(details: I) -> SuiteBuilder {
+        let mut builder = SuiteBuilder::new();
+        builder.extend(details);
+        builder
+    }
+}
+
+/// Builds a suite of TAP version 14 out of nothing but test points, with a plan for all of them.
+impl FromIterator<TestPoint> for TestSuite {
+    fn from_iter<I: IntoIterator<Item = TestPoint>>(points: I) -> TestSuite {
+        points.into_iter().map(TestDetails::TestPoint).collect::<SuiteBuilder>().finish()
+    }
+}
+
+impl IntoIterator for TestSuite {
+    type Item = TestDetails;
+    type IntoIter = std::vec::IntoIter<TestDetails>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.details.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TestSuite {
+    type Item = &'a TestDetails;
+    type IntoIter = std::slice::Iter<'a, TestDetails>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.details.iter()
+    }
+}
+
+/// Parses a whole run that is already in memory as bytes, e.g. a test fixture.
+pub fn parse_bytes(input: &[u8]) -> Result<TestSuite, ParseError> {
+    let input = std::str::from_utf8(input).map_err(ParseError::InvalidUtf8)?;
+    TestSuite::parse(input)
+}
+
+/// Combines several runs into one, e.g. the output of each test binary of a build. Test points are
+/// renumbered in order and the plans are summed up into a single leading plan, comments and other
+/// details of each run are kept. A run without a plan contributes its number of test points.
+pub fn merge(suites: Vec<TestSuite>) -> TestSuite {
+    let version = suites.iter().map(|suite| suite.version).max().unwrap_or(14);
+    let total = suites
+        .iter()
+        .map(|suite| suite.plan.as_ref().map_or_else(|| suite.test_points().len(), TestPlan::count))
+        .sum();
+
+    let mut details = vec![TestDetails::TestPlan(TestPlan::new(total))];
+    let mut next = 1;
+    for suite in suites {
+        for detail in suite.details {
+            match detail {
+                TestDetails::TestPlan(_) => {}
+                TestDetails::TestPoint(point) => {
+                    details.push(TestDetails::TestPoint(TestPoint { test_number: Some(next), ..point }));
+                    next += 1;
+                }
+                detail => details.push(detail),
+            }
+        }
+    }
+
+    TestSuite::from_details(version, details)
+}
+
+/// Whether every test number has the same status across all the runs, e.g. to spot flaky tests
+/// over repeated runs of the same suite.
+pub fn is_stable(runs: &[TestSuite]) -> bool {
+    unstable_numbers(runs).is_empty()
+}
+
+/// The test numbers whose status differs between the runs, in ascending order. Only explicitly
+/// numbered points are compared.
+pub fn unstable_numbers(runs: &[TestSuite]) -> Vec<usize> {
+    let mut statuses = BTreeMap::new();
+    let mut unstable = BTreeSet::new();
+
+    let points = runs.iter().flat_map(|run| run.test_points());
+    for point in points {
+        let Some(number) = point.test_number else {
+            continue;
+        };
+        if *statuses.entry(number).or_insert(point.status) != point.status {
+            unstable.insert(number);
+        }
+    }
+
+    unstable.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_mixed_suite() {
+        let input = "TAP Version 14\n1..4\nok 1 - first\n# a comment\nnot ok 2 - second\nnot ok 3 - third # TODO later\nok 4 - fourth # SKIP\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        assert_eq!(suite.version, 14);
+        assert_eq!(suite.plan, Some(TestPlan::new(4)));
+        assert_eq!(suite.bailed_out, None);
+
+        let failures: Vec<_> = suite.failures().iter().map(|point| point.test_number).collect();
+        assert_eq!(failures, vec![Some(2)]);
+        assert_eq!(suite.passed().len(), 1);
+        assert_eq!(suite.summary(), Summary { total: 4, passed: 1, failed: 1, skipped: 1, todo: 1 });
+    }
+
+    #[test]
+    fn partition_by_outcome() {
+        let input = "1..7\nok 1\nnot ok 2\nok 3 # SKIP\nnot ok 4 # TODO\nok 5\nok 6 # TODO\nnot ok 7\n";
+        let suite = TestSuite::parse(input).unwrap();
+        let partitioned = suite.partition();
+
+        let numbers = |points: &[&TestPoint]| points.iter().map(|point| point.test_number.unwrap()).collect::<Vec<_>>();
+        assert_eq!(numbers(&partitioned.passed), vec![1, 5]);
+        assert_eq!(numbers(&partitioned.failed), vec![2, 7]);
+        assert_eq!(numbers(&partitioned.skipped), vec![3]);
+        assert_eq!(numbers(&partitioned.todo), vec![4, 6]);
+    }
+
+    #[test]
+    fn strip_comments() {
+        let mut suite = TestSuite::parse("1..2\n# first up\nok 1 - a\n\n# then\nok 3 - b\n").unwrap();
+        suite.strip_comments();
+
+        assert_eq!(suite.details, vec![
+            TestDetails::TestPlan(TestPlan::new(2)),
+            TestDetails::TestPoint(TestPoint::ok("a").with_number(1)),
+            TestDetails::TestPoint(TestPoint::ok("b").with_number(3)),
+        ]);
+
+        suite.retain(|detail| !matches!(detail, TestDetails::TestPlan(_)));
+        assert_eq!(suite.plan, None);
+    }
+
+    #[test]
+    fn same_outcomes() {
+        let suite = TestSuite::parse("1..2\nok 1\nnot ok 2\n  ---\n  got: 1\n  ...\n").unwrap();
+        let noisy = TestSuite::parse("1..2\n# first\nok 1\nnot ok 2\n  ---\n  got: 2\n  ...\n").unwrap();
+        let fixed = TestSuite::parse("1..2\nok 1\nok 2\n").unwrap();
+
+        assert!(suite.same_outcomes(&noisy));
+        assert!(!suite.same_outcomes(&fixed));
+    }
+
+    #[test]
+    fn number_points() {
+        let mut suite = TestSuite::parse("ok\nok 5\n# comment\nok\nnot ok\n").unwrap();
+        suite.number_points();
+
+        let numbers: Vec<_> = suite.test_points().iter().map(|point| point.test_number).collect();
+        assert_eq!(numbers, vec![Some(1), Some(5), Some(6), Some(7)]);
+
+        let mut suite = TestSuite::parse(&format!("ok {}\nok\nok 3\nok\n", usize::MAX)).unwrap();
+        suite.number_points();
+        let numbers: Vec<_> = suite.test_points().iter().map(|point| point.test_number).collect();
+        assert_eq!(numbers, vec![Some(usize::MAX), None, Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn sparkline() {
+        let input = "ok 1\nok 2\nnot ok 3\n# comment\nok 4 # SKIP\nnot ok 5 # TODO\nok 6\n";
+        let suite = TestSuite::parse(input).unwrap();
+        assert_eq!(suite.sparkline(), "..Fst.");
+    }
+
+    #[test]
+    fn missing_numbers() {
+        let suite = TestSuite::parse("1..5\nok 1\nok 2\nnot ok 4\n").unwrap();
+        assert_eq!(suite.missing_numbers(), vec![3..=3, 5..=5]);
+
+        let suite = TestSuite::parse("1..6\nok 3\nok 4\nok 9\n").unwrap();
+        assert_eq!(suite.missing_numbers(), vec![1..=2, 5..=6]);
+
+        let suite = TestSuite::parse("1..4294967295\n").unwrap();
+        assert_eq!(suite.missing_numbers(), vec![1..=4_294_967_295]);
+    }
+
+    #[test]
+    fn time_weighted_pass_rate() {
+        let point = |status: bool, duration_ms: u32| TestDetails::TestPoint(TestPoint {
+            status,
+            test_number: None,
+            description: None,
+            directive: None,
+            yaml: Some(format!("duration_ms: {duration_ms}\n")),
+            truncated_yaml: false,
+            comments: Vec::new(),
+            #[cfg(feature = "serde")]
+            extra: None,
+        });
+        let suite = TestSuite::from_details(14, vec![point(true, 10), point(true, 10), point(false, 80)]);
+
+        let unweighted = suite.passed().len() as f64 / suite.test_points().len() as f64;
+        let weighted = suite.time_weighted_pass_rate().unwrap();
+        assert!((weighted - 0.2).abs() < f64::EPSILON);
+        assert!(weighted < unweighted);
+    }
+
+    #[test]
+    fn time_weighted_pass_rate_without_durations() {
+        let suite = TestSuite::parse("ok 1\nnot ok 2\n").unwrap();
+        assert_eq!(suite.time_weighted_pass_rate(), None);
+    }
+
+    #[test]
+    fn flaky_runs() {
+        let runs: Vec<_> = ["ok 1\nok 2\nok 3\n", "ok 1\nnot ok 2\nok 3\n", "ok 1\nok 2\nok 3\n"]
+            .into_iter()
+            .map(|run| TestSuite::parse(run).unwrap())
+            .collect();
+
+        assert!(!is_stable(&runs));
+        assert_eq!(unstable_numbers(&runs), vec![2]);
+        assert!(is_stable(&[runs[0].clone(), runs[2].clone()]));
+    }
+
+    #[test]
+    fn parse_bytes_literal() {
+        let suite = parse_bytes(b"TAP Version 14\n1..2\nok 1 - first\nnot ok 2 - second\n").unwrap();
+        assert_eq!(suite.summary(), Summary { total: 2, passed: 1, failed: 1, skipped: 0, todo: 0 });
+
+        let err = parse_bytes(b"TAP Version 14\nok 1 - \xff\n").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidUtf8(_)));
+    }
+
+    #[test]
+    fn exit_code() {
+        let todo_failure = TestSuite::parse("TAP Version 14\n1..2\nok 1\nnot ok 2 # TODO later\n").unwrap();
+        assert_eq!(todo_failure.exit_code(), 0);
+
+        let failure = TestSuite::parse("TAP Version 14\n1..2\nok 1\nnot ok 2\n").unwrap();
+        assert_ne!(failure.exit_code(), 0);
+
+        let todo_pass = TestSuite::parse("TAP Version 14\n1..1\nok 1 # TODO later\n").unwrap();
+        assert_eq!(todo_pass.exit_code(), 0);
+        assert_ne!(todo_pass.exit_code_with(true), 0);
+    }
+
+    #[test]
+    fn merge_two_suites() {
+        let first = TestSuite::parse("TAP Version 14\n1..2\nok 1 - a\n# from the first\nok 2 - b\n").unwrap();
+        let second = TestSuite::parse("TAP Version 14\n1..2\nnot ok 1 - c\nok 2 - d\n").unwrap();
+
+        let merged = merge(vec![first, second]);
+
+        assert_eq!(merged.plan, Some(TestPlan::new(4)));
+        let points: Vec<_> = merged.test_points()
+            .into_iter()
+            .map(|point| (point.test_number, point.description.as_deref()))
+            .collect();
+        assert_eq!(points, vec![(Some(1), Some("a")), (Some(2), Some("b")), (Some(3), Some("c")), (Some(4), Some("d"))]);
+        assert!(merged.details.contains(&TestDetails::Comment(crate::Comment("from the first".into()))));
+        assert_eq!(merged.summary().failed, 1);
+    }
+
+    #[test]
+    fn iterate_suite() {
+        let suite = TestSuite::parse("TAP Version 14\n1..2\nok 1\n# a comment\nnot ok 2\n").unwrap();
+        assert_eq!(suite.points().count(), 2);
+
+        let mut points = 0;
+        for detail in &suite {
+            if let TestDetails::TestPoint(_) = detail {
+                points += 1;
+            }
+        }
+        assert_eq!(points, 2);
+
+        let details: Vec<_> = suite.into_iter().collect();
+        assert_eq!(details.len(), 4);
+    }
+
+    #[test]
+    fn build_suite_from_points() {
+        let points = (1..=3).map(|number| TestDetails::TestPoint(TestPoint::ok(format!("point {number}")).with_number(number)));
+
+        let suite = points.collect::<SuiteBuilder>().finish();
+
+        assert_eq!(suite.plan, Some(TestPlan::new(3)));
+        assert_eq!(suite.test_points().len(), 3);
+        assert_eq!(suite.version, 14);
+    }
+
+    #[test]
+    fn collect_suite_from_points() {
+        let suite: TestSuite = [TestPoint::ok("first"), TestPoint::not_ok("second"), TestPoint::ok("third")]
+            .into_iter()
+            .collect();
+
+        assert_eq!(suite.version, 14);
+        assert_eq!(suite.plan, Some(TestPlan::new(3)));
+        assert_eq!(suite.details[0], TestDetails::TestPlan(TestPlan::new(3)));
+        assert_eq!(suite.summary().failed, 1);
+    }
+
+    #[test]
+    fn fingerprint_ignores_diagnostics() {
+        let first = TestSuite::parse("TAP Version 14\n1..2\nok 1 - a\nnot ok 2 - b\n  ---\n  got: 1\n  ...\n").unwrap();
+        let second = TestSuite::parse("TAP Version 14\n1..2\n# noise\nok 1 - a\nnot ok 2 - b\n  ---\n  got: 2\n  ...\n").unwrap();
+        let changed = TestSuite::parse("TAP Version 14\n1..2\nok 1 - a\nok 2 - b\n").unwrap();
+
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_ne!(first.fingerprint(), changed.fingerprint());
+    }
+
+    #[test]
+    fn fingerprint_is_pinned() {
+        let suite = TestSuite::parse("TAP Version 14\n1..3\nok 1 - a\nnot ok 2 - b\nok 3 # SKIP later\n").unwrap();
+        assert_eq!(suite.fingerprint(), 709_118_026_828_291_609);
+    }
+}
@@ -0,0 +1,87 @@
+//! Instrumentation of the parser, to debug harnesses with odd output.
+
+use tracing::{field, trace_span, warn, Span};
+use crate::TestDetails;
+
+/// A span for the work on a single detail, entered while it is parsed. What the detail turned out
+/// to be is recorded by [`detail`] once it is known.
+pub(crate) fn span() -> Span {
+    trace_span!("detail", variant = field::Empty, number = field::Empty, status = field::Empty)
+}
+
+/// Records a detail about to be emitted by the parser on the current span.
+pub(crate) fn detail(detail: &TestDetails) {
+    let span = Span::current();
+    span.record("variant", variant(detail));
+    if let TestDetails::TestPoint(point) = detail {
+        span.record("number", point.test_number);
+        span.record("status", point.status);
+    }
+
+    if let TestDetails::Anything(line) = detail {
+        warn!(line = line.as_str(), "line isn't valid TAP");
+    }
+}
+
+fn variant(detail: &TestDetails) -> &'static str {
+    match detail {
+        TestDetails::TestPoint(_) => "test_point",
+        TestDetails::BailOut(_) => "bail_out",
+        TestDetails::TestPlan(_) => "test_plan",
+        TestDetails::Pragma(_) => "pragma",
+        TestDetails::Comment(_) => "comment",
+        TestDetails::Empty => "empty",
+        TestDetails::Anything(_) => "anything",
+        TestDetails::Subtest(_) => "subtest",
+        TestDetails::ExtraTest { .. } => "extra_test",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tracing::{span, Event, Level, Metadata, Subscriber};
+    use crate::TestSuite;
+
+    /// Counts the warnings and the spans, everything else is ignored.
+    struct WarnCounter(Arc<AtomicUsize>, Arc<AtomicUsize>);
+
+    impl Subscriber for WarnCounter {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+            let spans = self.1.fetch_add(1, Ordering::SeqCst);
+            span::Id::from_u64(spans as u64 + 1)
+        }
+
+        fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            if *event.metadata().level() == Level::WARN {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    #[test]
+    fn warn_on_unrecognized_line() {
+        let (warnings, spans) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        tracing::subscriber::with_default(WarnCounter(warnings.clone(), spans.clone()), || {
+            TestSuite::parse("TAP Version 14\nok 1\nthis is not tap\n").unwrap();
+        });
+
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+        // one span for each detail
+        assert_eq!(spans.load(Ordering::SeqCst), 2);
+    }
+}
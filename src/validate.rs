@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use crate::TestDetails;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Violation {
+    /// The same test number was reported both passing and failing.
+    ContradictoryResults(usize),
+    /// The input ended in the middle of a test point's yaml block, holds the point's number.
+    UnterminatedYaml(Option<usize>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum NumberAnomaly {
+    /// The test number was used by more than one test point.
+    Duplicate(usize),
+    /// The test number is lower than the one before it.
+    OutOfOrder { expected: usize, found: usize },
+    /// Numbers were skipped between the two test numbers, a run starting past 1 has a gap from 0.
+    Gap { from: usize, to: usize },
+}
+
+/// Checks the explicit test numbers of a run in document order, points without a number are legal
+/// and ignored. TAP doesn't require numbers to be ordered, so these are warnings at most.
+pub fn check_numbers(details: &[TestDetails]) -> Vec<NumberAnomaly> {
+    let mut anomalies = Vec::new();
+    let mut seen = HashSet::new();
+    let mut previous = 0;
+
+    let numbers = details.iter().filter_map(|detail| match detail {
+        TestDetails::TestPoint(point) => point.test_number,
+        _ => None,
+    });
+    for number in numbers {
+        if !seen.insert(number) {
+            anomalies.push(NumberAnomaly::Duplicate(number));
+        } else if number < previous {
+            anomalies.push(NumberAnomaly::OutOfOrder { expected: previous + 1, found: number });
+        } else if number > previous + 1 {
+            anomalies.push(NumberAnomaly::Gap { from: previous, to: number });
+        }
+        previous = number;
+    }
+
+    anomalies
+}
+
+/// Checks a run for problems that the grammar alone can't catch, reporting everything it finds
+/// in document order.
+pub fn validate(details: &[TestDetails]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    // the status each test number was last seen with
+    let mut statuses = HashMap::new();
+
+    for detail in details {
+        let TestDetails::TestPoint(point) = detail else {
+            continue;
+        };
+        if point.truncated_yaml {
+            violations.push(Violation::UnterminatedYaml(point.test_number));
+        }
+        let Some(number) = point.test_number else {
+            continue;
+        };
+
+        let violation = Violation::ContradictoryResults(number);
+        match statuses.insert(number, point.status) {
+            Some(status) if status != point.status && !violations.contains(&violation) => {
+                violations.push(violation);
+            }
+            _ => {}
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_document;
+
+    #[test]
+    fn contradictory_results() {
+        let details = parse_document("ok 1\nnot ok 1\n").unwrap();
+        assert_eq!(validate(&details), vec![Violation::ContradictoryResults(1)]);
+    }
+
+    #[test]
+    fn unterminated_yaml() {
+        let details = parse_document("ok 1\nnot ok 2\n  ---\n  message: cut off\n").unwrap();
+        assert_eq!(validate(&details), vec![Violation::UnterminatedYaml(Some(2))]);
+    }
+
+    #[test]
+    fn plain_duplicate_is_not_contradictory() {
+        let details = parse_document("ok 1\nok 1\n").unwrap();
+        assert_eq!(validate(&details), vec![]);
+    }
+
+    #[test]
+    fn duplicate_numbers() {
+        let details = parse_document("ok 1\nok 1\n").unwrap();
+        assert_eq!(check_numbers(&details), vec![NumberAnomaly::Duplicate(1)]);
+    }
+
+    #[test]
+    fn unnumbered_points_are_not_duplicates() {
+        let details = parse_document("ok\nok\n").unwrap();
+        assert_eq!(check_numbers(&details), vec![]);
+    }
+
+    #[test]
+    fn gapped_numbers() {
+        let details = parse_document("ok 1\nok 3\n").unwrap();
+        assert_eq!(check_numbers(&details), vec![NumberAnomaly::Gap { from: 1, to: 3 }]);
+    }
+
+    #[test]
+    fn descending_numbers() {
+        let details = parse_document("ok 1\nok 3\nok 2\n").unwrap();
+
+        let expected = vec![
+            NumberAnomaly::Gap { from: 1, to: 3 },
+            NumberAnomaly::OutOfOrder { expected: 4, found: 2 },
+        ];
+        assert_eq!(check_numbers(&details), expected);
+    }
+}
@@ -0,0 +1,246 @@
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use futures::{stream, Stream, StreamExt};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use crate::decoder::{Decoded, Decoder};
+use crate::parsing::parse_version;
+use crate::{ParserOptions, TestDetails};
+
+/// What happened to the watched file since it was last looked at.
+enum Change {
+    Appended(Vec<u8>),
+    /// The file was truncated or replaced, its content starts over.
+    Restarted,
+    Failed(io::Error),
+}
+
+/// Parses the file at `path`, then follows what gets appended to it, like `tail -f`. Each detail
+/// is emitted once the line after it has been appended. Should the file be truncated or replaced,
+/// e.g. by log rotation, parsing starts over with the new content. While there is no file at
+/// `path`, as between rotation moving it away and creating the new one, it is waited for.
+///
+/// The directory holding the file is watched for file system notifications, the file is only read
+/// when one of them concerns it. Watching stops once the stream is dropped.
+pub fn watch(path: &Path) -> impl Stream<Item = io::Result<TestDetails>> {
+    let (tx, rx) = unbounded();
+    let name = path.file_name().map(ToOwned::to_owned);
+    // the file is only read in response to a notification, the first time it's as if it changed
+    let _ = tx.unbounded_send(Ok(()));
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let concerns_file = |event: &notify::Event| event.paths.iter().any(|path| path.file_name() == name.as_deref());
+        match event {
+            Ok(event) if !concerns_file(&event) => {}
+            Ok(_) => drop(tx.unbounded_send(Ok(()))),
+            Err(e) => drop(tx.unbounded_send(Err(io::Error::other(e)))),
+        }
+    });
+    // the file might not be there yet, but the directory it is going to be created in is
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let watcher = watcher.and_then(|mut watcher| {
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+
+    let state = watcher.map_err(io::Error::other).map(|watcher| Watched {
+        _watcher: watcher,
+        notifications: rx,
+        file: Followed::new(path),
+        run: Run::default(),
+    });
+    stream::unfold(Some(state), |state| async move {
+        let mut watched = match state? {
+            Ok(watched) => watched,
+            Err(e) => return Some((Err(e), None)),
+        };
+
+        loop {
+            if let Decoded::Detail(detail) = watched.run.decode() {
+                return Some((detail, Some(Ok(watched))));
+            }
+
+            if let Err(e) = watched.notifications.next().await? {
+                return Some((Err(e), None));
+            }
+            for change in watched.file.changes() {
+                match change {
+                    Change::Appended(bytes) => watched.run.feed(&bytes),
+                    Change::Restarted => watched.run = Run::default(),
+                    Change::Failed(e) => return Some((Err(e), None)),
+                }
+            }
+        }
+    })
+}
+
+/// The state of a watch, the watcher has to be kept around for notifications to keep coming.
+struct Watched {
+    _watcher: RecommendedWatcher,
+    notifications: UnboundedReceiver<io::Result<()>>,
+    file: Followed,
+    run: Run,
+}
+
+/// How far the file has been read.
+struct Followed {
+    path: PathBuf,
+    position: u64,
+    identity: Option<u64>,
+    /// Whatever shows up at the path after it went missing is a new file.
+    missing: bool,
+}
+
+impl Followed {
+    fn new(path: &Path) -> Followed {
+        Followed { path: path.to_path_buf(), position: 0, identity: None, missing: false }
+    }
+
+    /// What changed since the file was last read.
+    fn changes(&mut self) -> Vec<Change> {
+        match read_appended(&self.path, &mut self.position, &mut self.identity) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                (self.position, self.identity, self.missing) = (0, None, true);
+                Vec::new()
+            }
+            Ok((restarted, appended)) => {
+                let restarted = (restarted || std::mem::take(&mut self.missing)).then_some(Change::Restarted);
+                let appended = (!appended.is_empty()).then_some(Change::Appended(appended));
+                restarted.into_iter().chain(appended).collect()
+            }
+            Err(e) => vec![Change::Failed(e)],
+        }
+    }
+}
+
+/// Reads whatever was appended to the file since `position`, or all of it if it was truncated or
+/// replaced since, which is reported as well.
+fn read_appended(path: &Path, position: &mut u64, identity: &mut Option<u64>) -> io::Result<(bool, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+
+    let replaced = identity.is_some() && *identity != file_identity(&metadata);
+    let restarted = replaced || metadata.len() < *position;
+    if restarted {
+        *position = 0;
+    }
+    *identity = file_identity(&metadata);
+
+    let mut appended = Vec::new();
+    file.seek(SeekFrom::Start(*position))?;
+    *position += file.read_to_end(&mut appended)? as u64;
+
+    Ok((restarted, appended))
+}
+
+/// Tells files apart, so that a file replaced by another one of the same path is noticed.
+#[cfg(unix)]
+fn file_identity(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// The content of the file since it was last (re)started.
+#[derive(Debug, Default)]
+struct Run {
+    /// The first line, until it is complete and known whether it is a version line.
+    header: Vec<u8>,
+    /// Decodes everything after the first line.
+    decoder: Option<Decoder>,
+}
+
+impl Run {
+    fn feed(&mut self, bytes: &[u8]) {
+        if let Some(decoder) = &mut self.decoder {
+            decoder.feed(bytes);
+            return;
+        }
+
+        self.header.extend_from_slice(bytes);
+        let Some(end) = self.header.iter().position(|&byte| byte == b'\n') else {
+            return;
+        };
+
+        let rest = self.header.split_off(end + 1);
+        let header = std::mem::take(&mut self.header);
+        let is_version = std::str::from_utf8(&header).is_ok_and(|line| parse_version(line).is_ok());
+        let (offset, first_line) = if is_version { (header.len(), Vec::new()) } else { (0, header) };
+
+        let mut decoder = Decoder::after_header(offset, first_line, ParserOptions::default());
+        // no bytes at all would end the input
+        if !rest.is_empty() {
+            decoder.feed(&rest);
+        }
+        self.decoder = Some(decoder);
+    }
+
+    fn decode(&mut self) -> Decoded {
+        match &mut self.decoder {
+            Some(decoder) => decoder.decode(),
+            None => Decoded::NeedData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use futures::executor::block_on;
+    use super::*;
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn next_description(details: &mut (impl Stream<Item = io::Result<TestDetails>> + Unpin)) -> Option<String> {
+        match block_on(details.next()) {
+            Some(Ok(TestDetails::TestPoint(point))) => point.description,
+            other => panic!("expected a test point, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn follow_appends_and_truncation() {
+        let path = std::env::temp_dir().join(format!("arnulf-watch-{}.tap", std::process::id()));
+        std::fs::write(&path, "TAP Version 14\nok 1 - first\n").unwrap();
+
+        let mut details = Box::pin(watch(&path));
+        append(&path, "ok 2 - second\n");
+        assert_eq!(next_description(&mut details).as_deref(), Some("first"));
+        append(&path, "ok 3 - third\n");
+        assert_eq!(next_description(&mut details).as_deref(), Some("second"));
+
+        // starting over drops the point that was still waiting for the line after it
+        std::fs::write(&path, "TAP Version 14\nok 1 - again\n# done\n").unwrap();
+        assert_eq!(next_description(&mut details).as_deref(), Some("again"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn follow_rotation_by_rename() {
+        let path = std::env::temp_dir().join(format!("arnulf-rotate-{}.tap", std::process::id()));
+        let rotated = path.with_extension("tap.1");
+        std::fs::write(&path, "TAP Version 14\nok 1 - first\nok 2 - second\n").unwrap();
+
+        let mut details = Box::pin(watch(&path));
+        assert_eq!(next_description(&mut details).as_deref(), Some("first"));
+
+        std::fs::rename(&path, &rotated).unwrap();
+        std::fs::write(&path, "TAP Version 14\nok 1 - new\n# done\n").unwrap();
+        assert_eq!(next_description(&mut details).as_deref(), Some("new"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+}
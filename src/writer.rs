@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+use crate::{BailOut, TestPoint, TestSuite};
+
+/// Writes TAP documents.
+#[derive(Debug)]
+pub struct Writer<W> {
+    out: W,
+}
+
+impl<W> Writer<W>
+    where
+        W: Write,
+{
+    pub fn new(out: W) -> Writer<W> {
+        Writer { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Writes a document holding only the failures of `suite`, numbered from 1 with a plan to
+    /// match. Their yaml diagnostics are kept, everything else is left out.
+    pub fn write_failures(&mut self, suite: &TestSuite) -> io::Result<()> {
+        let failures = suite.failures();
+
+        writeln!(self.out, "TAP Version 14")?;
+        writeln!(self.out, "1..{}", failures.len())?;
+        for (index, point) in failures.into_iter().enumerate() {
+            let point = TestPoint { comments: Vec::new(), ..point.clone() }.with_number(index + 1);
+            writeln!(self.out, "{point}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `suite` in canonical form: a version line, a leading plan, the top level test points
+    /// with their yaml blocks, and the bail out if the run gave up. Comments, pragmas and subtests
+    /// are left out.
+    ///
+    /// The plan is the suite's own, a run without one is planned for the points it has. Points keep
+    /// their numbers, unnumbered ones are numbered as [`TestSuite::number_points`] does.
+    pub fn write_canonical(&mut self, suite: &TestSuite) -> io::Result<()> {
+        let mut numbered = suite.clone();
+        numbered.number_points();
+        let points = numbered.test_points();
+
+        writeln!(self.out, "TAP Version 14")?;
+        match &suite.plan {
+            Some(plan) => write!(self.out, "{}..{}", plan.start, plan.end)?,
+            None => write!(self.out, "1..{}", points.len())?,
+        }
+        match suite.plan.as_ref().map(|plan| (&plan.directive, &plan.reason)) {
+            Some((Some(directive), _)) => write!(self.out, " {directive}")?,
+            Some((None, Some(reason))) => write!(self.out, " # {reason}")?,
+            _ => {}
+        }
+        writeln!(self.out)?;
+
+        for point in points {
+            writeln!(self.out, "{point}")?;
+        }
+        match &suite.bailed_out {
+            Some(BailOut(Some(reason))) => writeln!(self.out, "Bail out! {reason}")?,
+            Some(BailOut(None)) => writeln!(self.out, "Bail out!")?,
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TestPlan;
+
+    #[test]
+    fn only_failures() {
+        let input = "TAP Version 14\n1..3\nok 1 - first\nnot ok 2 - second\n  ---\n  got: 1\n  ...\nok 3 - third\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_failures(&suite).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(output, "TAP Version 14\n1..1\nnot ok 1 - second\n  ---\n  got: 1\n  ...\n");
+
+        let failures = TestSuite::parse(&output).unwrap();
+        assert_eq!(failures.plan, Some(TestPlan::new(1)));
+        assert_eq!(failures.summary().failed, 1);
+        assert_eq!(failures.summary().total, 1);
+    }
+
+    #[test]
+    fn canonical_form() {
+        let input = "# producer chatter\nok - first\nnot ok - second #todo later\n  ---\n  got: 1\n  ...\nok 3 - third\n1..3\n";
+        let suite = TestSuite::parse(input).unwrap();
+
+        let output = suite.to_canonical_tap();
+        assert_eq!(
+            output,
+            "TAP Version 14\n1..3\nok 1 - first\nnot ok 2 - second # TODO later\n  ---\n  got: 1\n  ...\nok 3 - third\n",
+        );
+
+        let mut numbered = suite.clone();
+        numbered.number_points();
+        assert!(TestSuite::parse(&output).unwrap().same_outcomes(&numbered));
+    }
+
+    #[test]
+    fn canonical_form_keeps_plan_and_numbers() {
+        let suite = TestSuite::parse("1..5
+ok 5 - a
+ok 2 - b
+ok - c
+").unwrap();
+
+        let output = suite.to_canonical_tap();
+        assert_eq!(output, "TAP Version 14\n1..5\nok 5 - a\nok 2 - b\nok 3 - c\n");
+
+        let canonical = TestSuite::parse(&output).unwrap();
+        let mut numbered = suite.clone();
+        numbered.number_points();
+        assert!(canonical.same_outcomes(&numbered));
+        assert_eq!(canonical.plan, suite.plan);
+        assert_eq!(canonical.missing_numbers(), vec![1..=1, 4..=4]);
+    }
+}
@@ -0,0 +1,84 @@
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::str::Lines;
+
+/// Looks up the value of a top level `key: value` pair in a yaml block.
+///
+/// This isn't a yaml parser, it only understands the flat scalar mappings TAP producers put in
+/// their diagnostics, which is all we need to pull out well known keys.
+pub(crate) fn scalar<'a>(yaml: &'a str, key: &str) -> Option<&'a str> {
+    find_key(yaml, key).map(|(value, _, _)| value)
+}
+
+/// Like [`scalar`], but also understands literal block scalars (`key: |`), joining their lines.
+pub(crate) fn text(yaml: &str, key: &str) -> Option<String> {
+    let (value, indent, lines) = find_key(yaml, key)?;
+    if !matches!(value, "|" | "|-") {
+        return Some(value.to_string());
+    }
+
+    // the block is made up of the lines after the key that are indented further than it
+    let block = lines
+        .take_while(|line| line.trim().is_empty() || indentation(line) > indent)
+        .collect::<Vec<_>>();
+    let block_indent = block.iter().filter(|line| !line.trim().is_empty()).map(|line| indentation(line)).min().unwrap_or(0);
+
+    let mut text = block
+        .iter()
+        .map(|line| line.get(block_indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if value == "|" {
+        text.push('\n');
+    }
+    Some(text)
+}
+
+/// Finds the top level `key`, returning its value, its indentation and the lines following it.
+fn find_key<'a>(yaml: &'a str, key: &str) -> Option<(&'a str, usize, Lines<'a>)> {
+    // top level keys are the least indented ones
+    let indent = yaml.lines().filter(|line| !line.trim().is_empty()).map(indentation).min()?;
+
+    let mut lines = yaml.lines();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() || indentation(line) != indent {
+            continue;
+        }
+        match line.trim_start().split_once(':') {
+            Some((name, value)) if name.trim_end() == key => return Some((value.trim(), indent, lines)),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalar_ignores_nested_keys() {
+        let yaml = "  data:\n    duration_ms: 5\n  duration_ms: 12\n";
+
+        assert_eq!(scalar(yaml, "duration_ms"), Some("12"));
+        assert_eq!(scalar(yaml, "missing"), None);
+    }
+
+    #[test]
+    fn text_joins_block_scalars() {
+        let yaml = "got: |\n  first\n    second\nexpected: plain\n";
+
+        assert_eq!(text(yaml, "got").as_deref(), Some("first\n  second\n"));
+        assert_eq!(text(yaml, "expected").as_deref(), Some("plain"));
+    }
+
+    #[test]
+    fn text_ignores_nested_keys() {
+        let yaml = "data:\n  got: |\n    nested\ngot: |\n  top\n";
+
+        assert_eq!(text(yaml, "got").as_deref(), Some("top\n"));
+    }
+}